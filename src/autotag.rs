@@ -0,0 +1,182 @@
+use std::{fmt, path::Path};
+
+use color_eyre::Result;
+
+use crate::{Track, library::ScanOptions, template::PathTemplate, track::CachedField};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A tag to write, along with the value it replaces
+pub(crate) struct TagChange {
+    pub field: CachedField,
+    /// The tagged value, which is empty if the tag is missing
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for TagChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: '{}' -> '{}'", self.field.name(), self.old, self.new)
+    }
+}
+
+/// The changes that would make the tags of `track` match its path within `root`
+///
+/// Empty if the path doesn't match the template, or the tags already match it. Only the part of
+/// the path inside the library root is matched, so the root's own directories aren't taken for
+/// tags.
+pub(crate) fn tag_changes(template: &PathTemplate, root: &Path, track: &Track) -> Vec<TagChange> {
+    let Some(captures) = track
+        .path
+        .strip_prefix(root)
+        .ok()
+        .and_then(|relative| template.captures(relative))
+    else {
+        return Vec::new();
+    };
+
+    captures
+        .into_iter()
+        .filter_map(|(field, new)| {
+            // Values inferred from the path aren't in the tags yet
+            let old = if track.is_missing(&field) || track.inferred.contains(&field) {
+                String::new()
+            } else {
+                track.cached_field_string(&field)
+            };
+            // Compare numbers by value, so `01` matches a track number of 1
+            let new = match field {
+                CachedField::DiscNumber | CachedField::TrackNumber | CachedField::Year => {
+                    new.parse::<u32>().map_or(new, |number| number.to_string())
+                }
+                _ => new,
+            };
+
+            (old != new).then_some(TagChange { field, old, new })
+        })
+        .collect()
+}
+
+/// Writes the changes to the track's file, updating `track` to match
+pub(crate) fn write_changes(track: &mut Track, changes: &[TagChange]) -> Result<()> {
+    let edits: Vec<_> = changes
+        .iter()
+        .map(|change| (change.field, change.new.clone()))
+        .collect();
+
+    track.write_tags(&edits)
+}
+
+/// Tags every track whose path in its library root matches the template, printing the changes
+///
+/// With `dry_run` set, only the changes are printed. Returns the tracks that were written.
+pub(crate) fn tag_tracks(
+    tracks: Vec<Track>,
+    options: &ScanOptions,
+    template: &PathTemplate,
+    dry_run: bool,
+) -> Vec<Track> {
+    let mut updated = Vec::new();
+    let mut changed = 0;
+    for mut track in tracks {
+        let Some(root) = options.root_of(&track.path) else {
+            continue;
+        };
+        let changes = tag_changes(template, &root.path, &track);
+        if changes.is_empty() {
+            continue;
+        }
+
+        changed += 1;
+        println!("{}", track.path.display());
+        for change in &changes {
+            println!("    {change}");
+        }
+
+        if dry_run {
+            continue;
+        }
+        match write_changes(&mut track, &changes) {
+            Ok(()) => updated.push(track),
+            Err(e) => eprintln!("Couldn't write tags to {}: {e}", track.path.display()),
+        }
+    }
+
+    if dry_run {
+        println!("{changed} tracks would be updated. Nothing was written (dry run).");
+    } else {
+        println!("Updated {} of {changed} tracks.", updated.len());
+    }
+
+    updated
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn changes_skip_matching_tags() {
+        let root = Path::new("/music");
+        let template = PathTemplate::parse("{artist}/{album}/{track} - {title}").unwrap();
+        let track = Track {
+            path: PathBuf::from("/music/Artist/Album/01 - Song.flac"),
+            artists: vec!["Artist".to_owned()],
+            album: Some("Album".to_owned()),
+            track_number: Some(1),
+            title: Some("Old Title".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            tag_changes(&template, root, &track),
+            [TagChange {
+                field: CachedField::Title,
+                old: "Old Title".to_owned(),
+                new: "Song".to_owned(),
+            }]
+        );
+
+        let untagged = Track {
+            path: PathBuf::from("/music/Artist/Album/01 - Song.flac"),
+            ..Default::default()
+        };
+        let fields: Vec<_> = tag_changes(&template, root, &untagged)
+            .into_iter()
+            .map(|change| (change.field, change.old))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                (CachedField::Artist, String::new()),
+                (CachedField::Album, String::new()),
+                (CachedField::TrackNumber, String::new()),
+                (CachedField::Title, String::new()),
+            ]
+        );
+
+        let elsewhere = Track {
+            path: PathBuf::from("/music/loose.flac"),
+            ..Default::default()
+        };
+        assert!(tag_changes(&template, root, &elsewhere).is_empty());
+    }
+
+    #[test]
+    fn changes_only_match_the_path_inside_the_root() {
+        let template = PathTemplate::parse("{genre}/{artist}/{album}/{title}").unwrap();
+        let track = Track {
+            path: PathBuf::from("/music/Artist/Album/x.flac"),
+            ..Default::default()
+        };
+
+        assert!(tag_changes(&template, Path::new("/music"), &track).is_empty());
+        assert!(tag_changes(&template, Path::new("/elsewhere"), &track).is_empty());
+        let fields: Vec<_> = tag_changes(&template, Path::new("/"), &track)
+            .into_iter()
+            .map(|change| (change.field, change.new))
+            .collect();
+        assert_eq!(fields[0], (CachedField::Genre, "music".to_owned()));
+    }
+}
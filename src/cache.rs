@@ -1,23 +1,171 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+};
 
-use color_eyre::Result;
+use color_eyre::{Result, eyre::eyre};
+use csv::StringRecord;
 
 use crate::Track;
 
-pub(crate) fn read_cache(path: &Path) -> Result<Vec<Track>> {
-    let file = fs::File::open(path)?;
-    let mut reader = csv::Reader::from_reader(file);
-    let tracks: Vec<Track> = reader.deserialize().flatten().collect();
+/// Version of the cache format written by this build
+///
+/// Bump this and add a migration to [`MIGRATIONS`] whenever a change to [`Track`] would make
+/// older caches read incorrectly, e.g., when a column changes meaning.
+const CACHE_VERSION: u32 = 2;
 
-    Ok(tracks)
+/// First line of the cache file, followed by the version number
+const VERSION_PREFIX: &str = "# minim library cache, version ";
+
+/// Converts rows from each version to the next, starting from version 1
+const MIGRATIONS: &[fn(&mut StringRecord, &mut [StringRecord])] = &[migrate_v1_durations];
+
+/// Tracks read from the cache
+pub(crate) struct Cache {
+    pub tracks: Vec<Track>,
+    /// Number of rows that couldn't be read
+    ///
+    /// Their tracks are missing from `tracks`, so the next scan reads their files again.
+    pub corrupted: usize,
 }
 
+pub(crate) fn read_cache(path: &Path) -> Result<Cache> {
+    let contents = fs::read_to_string(path)?;
+
+    // Caches written before versioning don't have a version line
+    let (version, rows) = match contents.strip_prefix(VERSION_PREFIX) {
+        Some(rest) => {
+            let (version, rows) = rest.split_once('\n').unwrap_or((rest, ""));
+            (version.trim().parse::<u32>()?, rows)
+        }
+        None => (1, contents.as_str()),
+    };
+    if !(1..=CACHE_VERSION).contains(&version) {
+        return Err(eyre!("Unsupported library cache version {version}"));
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(rows.as_bytes());
+    let mut headers = reader.headers()?.clone();
+    let mut corrupted = 0;
+    let mut records: Vec<StringRecord> = reader
+        .records()
+        .filter_map(|record| record.inspect_err(|_| corrupted += 1).ok())
+        .collect();
+
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(&mut headers, &mut records);
+    }
+
+    let mut tracks = Vec::with_capacity(records.len());
+    for record in records {
+        match record.deserialize::<Track>(Some(&headers)) {
+            Ok(track) => tracks.push(track),
+            Err(_) => corrupted += 1,
+        }
+    }
+
+    Ok(Cache { tracks, corrupted })
+}
+
+/// Writes the cache to a temporary file first, so a crash can't leave it half-written
 pub(crate) fn write_cache(path: &Path, tracks: &[Track]) -> Result<()> {
-    let file = fs::File::create(path)?;
+    let temp_path = path.with_extension("csv.tmp");
+    let file = fs::File::create(&temp_path)?;
+
+    let mut file = BufWriter::new(file);
+    writeln!(file, "{VERSION_PREFIX}{CACHE_VERSION}")?;
     let mut writer = csv::Writer::from_writer(file);
     for track in tracks {
         writer.serialize(track)?;
     }
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+    fs::rename(&temp_path, path)?;
 
     Ok(())
 }
+
+/// Version 1 stored durations in whole seconds, in a `duration` column
+fn migrate_v1_durations(headers: &mut StringRecord, records: &mut [StringRecord]) {
+    let Some(index) = headers.iter().position(|header| header == "duration") else {
+        return;
+    };
+
+    *headers = headers
+        .iter()
+        .map(|header| match header {
+            "duration" => "duration_ms",
+            header => header,
+        })
+        .collect();
+
+    for record in records {
+        // Leave unparseable values alone, so the row is reported as corrupted
+        let Some(millis) = record
+            .get(index)
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .map(|secs| (secs * 1000).to_string())
+        else {
+            continue;
+        };
+
+        *record = record
+            .iter()
+            .enumerate()
+            .map(|(i, field)| if i == index { millis.as_str() } else { field })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// A cache file in a new temporary directory, which is removed when it's dropped
+    fn temp_cache(contents: &str) -> (TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.csv");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn migrates_unversioned_cache() {
+        let (_dir, path) = temp_cache(
+            "path,title,artist,album,duration\n\
+             /music/a.flac,A,Artist,Album,187\n\
+             /music/b.flac,B,Artist,Album,not a number\n",
+        );
+        let cache = read_cache(&path).unwrap();
+
+        assert_eq!(cache.tracks.len(), 1);
+        assert_eq!(cache.tracks[0].duration, Duration::from_secs(187));
+        assert_eq!(cache.corrupted, 1);
+    }
+
+    #[test]
+    fn roundtrip_writes_version() {
+        let (_dir, path) = temp_cache("");
+        let track = Track {
+            path: "/music/a.flac".into(),
+            duration: Duration::from_millis(1500),
+            ..Default::default()
+        };
+
+        write_cache(&path, std::slice::from_ref(&track)).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let cache = read_cache(&path).unwrap();
+
+        assert!(contents.starts_with(&format!("{VERSION_PREFIX}{CACHE_VERSION}\n")));
+        assert_eq!(cache.tracks[0].duration, track.duration);
+        assert_eq!(cache.corrupted, 0);
+    }
+}
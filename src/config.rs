@@ -4,13 +4,140 @@ use std::{
 };
 
 use color_eyre::eyre::{self, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use toml_edit::DocumentMut;
+
+use crate::{
+    paths,
+    track::{CachedField, SortKey},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "LibraryRootEntry")]
+/// A directory to look for music in
+///
+/// Paths in the config file can start with `~` and use environment variables like `$HOME` or
+/// `${XDG_MUSIC_DIR}`.
+pub(crate) struct LibraryRoot {
+    pub path: PathBuf,
+    /// Name to show for tracks from this directory, e.g., "NAS"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl LibraryRoot {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, label: None }
+    }
+
+    /// The label, or the directory's name if there isn't one
+    pub fn name(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self
+                .path
+                .file_name()
+                .unwrap_or(self.path.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+/// A library root can be written as just a path, or as a table with a label
+enum LibraryRootEntry {
+    Path(PathBuf),
+    Root {
+        path: PathBuf,
+        label: Option<String>,
+    },
+}
+
+impl TryFrom<LibraryRootEntry> for LibraryRoot {
+    type Error = eyre::Report;
+
+    fn try_from(entry: LibraryRootEntry) -> Result<Self> {
+        let (path, label) = match entry {
+            LibraryRootEntry::Path(path) => (path, None),
+            LibraryRootEntry::Root { path, label } => (path, label),
+        };
+
+        Ok(Self {
+            path: paths::expand_path(&path)?,
+            label,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Where the library is stored between runs
+pub(crate) enum LibraryBackend {
+    /// `library.csv`, which is loaded and sorted in memory
+    #[default]
+    Csv,
+    /// `library.db`, an SQLite database that also holds play history, ratings and playlists
+    Sqlite,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct Config {
-    pub library_root: PathBuf,
+    /// Directories to look for music in
+    ///
+    /// Also accepts a single `library_root`, as written by older versions.
+    #[serde(alias = "library_root", deserialize_with = "Config::one_or_many")]
+    pub library_roots: Vec<LibraryRoot>,
+    /// Name of a theme in the themes directory, or the path to a theme file
     pub theme: String,
     pub show_track_art: bool,
+    /// Columns shown in the library and search results tables
+    #[serde(default = "Config::default_library_columns")]
+    pub library_columns: Vec<CachedField>,
+    /// How the library table is sorted
+    #[serde(default = "SortKey::album_order")]
+    pub library_sort: Vec<SortKey>,
+    /// Leading articles to ignore when sorting, e.g., `["The", "A"]`
+    #[serde(default)]
+    pub ignored_articles: Vec<String>,
+    /// Filename patterns for cover art next to the audio files, in order of preference
+    ///
+    /// Only used for tracks without embedded pictures.
+    #[serde(default = "Config::default_cover_art_patterns")]
+    pub cover_art_patterns: Vec<String>,
+    /// Whether to watch the library for changes while the player is running
+    #[serde(default)]
+    pub watch_library: bool,
+    #[serde(default)]
+    pub library_backend: LibraryBackend,
+    /// Extensions of files to read as tracks, ignoring case
+    #[serde(default = "Config::default_audio_extensions")]
+    pub audio_extensions: Vec<String>,
+    /// gitignore-style patterns for files and directories to skip, relative to each library root
+    ///
+    /// Each directory can also have a `.minimignore` file with more patterns.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Whether to scan the targets of symbolic links
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Patterns like `{artist}/{album}/{track} - {title}` for filling in missing tags from each
+    /// track's path within its library root
+    #[serde(default)]
+    pub path_templates: Vec<String>,
+    /// How many seconds to skip when seeking forward or back
+    #[serde(default = "Config::default_seek_step")]
+    pub seek_step: u64,
+    /// Whether tracks fade into each other, unless they're from the same album
+    #[serde(default)]
+    pub crossfade: bool,
+    /// How many seconds the crossfade lasts
+    #[serde(default = "Config::default_crossfade_duration")]
+    pub crossfade_duration: u64,
+    /// Whether changing the playback speed keeps the pitch the same
+    #[serde(default = "Config::default_preserve_pitch")]
+    pub preserve_pitch: bool,
 }
 
 impl Config {
@@ -19,6 +146,131 @@ impl Config {
 
         Config::from_str(&s)
     }
+
+    /// The file the theme is loaded from
+    ///
+    /// A `theme` with a path separator, or starting with `~` or `$`, is a path and is expanded
+    /// like the library roots.
+    pub fn theme_path(&self) -> Result<PathBuf> {
+        if self.theme.contains(['/', '\\']) || self.theme.starts_with(['~', '$']) {
+            return paths::expand_path(Path::new(&self.theme));
+        }
+
+        let mut path =
+            paths::theme_dir().ok_or_else(|| eyre::eyre!("Couldn't find the config directory"))?;
+        path.push(format!("{}.toml", self.theme));
+        Ok(path)
+    }
+
+    /// The configured paths that don't exist, with the option that each one is from
+    pub fn missing_paths(&self) -> Vec<(&'static str, PathBuf)> {
+        let roots = self
+            .library_roots
+            .iter()
+            .map(|root| ("library_roots", root.path.clone()));
+        let theme = self.theme_path().ok().map(|path| ("theme", path));
+
+        roots
+            .chain(theme)
+            .filter(|(_, path)| !path.exists())
+            .collect()
+    }
+
+    /// Sets `library_sort` in the config file at `path`
+    ///
+    /// Only that setting is rewritten, so comments, formatting and paths with `~` or environment
+    /// variables in the rest of the file are kept as they are.
+    pub fn save_library_sort(path: &Path, sort: &[SortKey]) -> Result<()> {
+        let mut document: DocumentMut = std::fs::read_to_string(path)?.parse()?;
+        document["library_sort"] = Self::library_sort_item(sort)?;
+        std::fs::write(path, document.to_string())?;
+
+        Ok(())
+    }
+
+    /// `sort` as an inline array of tables, e.g., `[{ field = "Year", direction = "Descending" }]`
+    fn library_sort_item(sort: &[SortKey]) -> Result<toml_edit::Item> {
+        #[derive(Serialize)]
+        struct LibrarySort<'a> {
+            library_sort: &'a [SortKey],
+        }
+
+        let document: DocumentMut =
+            toml::to_string(&LibrarySort { library_sort: sort })?.parse()?;
+        let mut value = match document.get("library_sort") {
+            Some(item) => item
+                .clone()
+                .into_value()
+                .map_err(|_| eyre::eyre!("Couldn't convert the library sort to TOML"))?,
+            // An empty list isn't written at all
+            None => toml_edit::Array::new().into(),
+        };
+        if let Some(array) = value.as_array_mut() {
+            array.fmt();
+        }
+
+        Ok(toml_edit::value(value))
+    }
+
+    fn default_library_columns() -> Vec<CachedField> {
+        vec![
+            CachedField::TrackNumber,
+            CachedField::Title,
+            CachedField::Artist,
+            CachedField::Album,
+            CachedField::Duration,
+        ]
+    }
+
+    fn one_or_many<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Vec<LibraryRoot>, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany {
+            One(LibraryRootEntry),
+            Many(Vec<LibraryRootEntry>),
+        }
+
+        let entries = match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(entry) => vec![entry],
+            OneOrMany::Many(entries) => entries,
+        };
+        // Converted here rather than while matching the untagged enum, which would hide the error
+        entries
+            .into_iter()
+            .map(|entry| LibraryRoot::try_from(entry).map_err(serde::de::Error::custom))
+            .collect()
+    }
+
+    fn default_cover_art_patterns() -> Vec<String> {
+        ["cover.*", "folder.*", "front.*"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn default_seek_step() -> u64 {
+        5
+    }
+
+    pub(crate) fn default_crossfade_duration() -> u64 {
+        5
+    }
+
+    fn default_preserve_pitch() -> bool {
+        true
+    }
+
+    fn default_audio_extensions() -> Vec<String> {
+        [
+            "aac", "aif", "aifc", "aiff", "ape", "flac", "m4a", "m4b", "mp3", "mpc", "oga", "ogg",
+            "opus", "spx", "wav", "wv",
+        ]
+        .into_iter()
+        .map(str::to_owned)
+        .collect()
+    }
 }
 
 impl FromStr for Config {
@@ -33,9 +285,164 @@ impl FromStr for Config {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            library_root: dirs::audio_dir().unwrap(),
+            library_roots: vec![LibraryRoot::new(dirs::audio_dir().unwrap())],
             theme: "default".to_owned(),
             show_track_art: true,
+            library_columns: Self::default_library_columns(),
+            library_sort: SortKey::album_order(),
+            ignored_articles: Vec::new(),
+            cover_art_patterns: Self::default_cover_art_patterns(),
+            watch_library: false,
+            library_backend: LibraryBackend::default(),
+            audio_extensions: Self::default_audio_extensions(),
+            exclude: Vec::new(),
+            follow_symlinks: false,
+            path_templates: Vec::new(),
+            seek_step: Self::default_seek_step(),
+            crossfade: false,
+            crossfade_duration: Self::default_crossfade_duration(),
+            preserve_pitch: Self::default_preserve_pitch(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn load_builtin_config() {
+        let config = Config::from_str(include_str!("../assets/config.toml")).unwrap();
+        assert_eq!(config.library_columns, Config::default_library_columns());
+    }
+
+    #[test]
+    fn library_columns_default_when_missing() {
+        let config = Config::from_str(
+            r#"
+            library_root = "/music"
+            theme = "default"
+            show_track_art = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.library_columns, Config::default_library_columns());
+    }
+
+    #[test]
+    fn library_sort_roundtrip() {
+        let mut config = Config::from_str(include_str!("../assets/config.toml")).unwrap();
+        config.library_sort = vec![
+            SortKey::descending(CachedField::Year),
+            SortKey::ascending(CachedField::Title),
+        ];
+        let s = toml::to_string(&config).unwrap();
+        assert_eq!(config, Config::from_str(&s).unwrap());
+    }
+
+    #[test]
+    fn saving_library_sort_keeps_the_rest_of_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "# My config\nlibrary_root = \"~/music\" # old name\n\n[extra]\nkey = 1\n";
+        std::fs::write(&path, original).unwrap();
+
+        let sort = [
+            SortKey::descending(CachedField::Year),
+            SortKey::ascending(CachedField::Title),
+        ];
+        Config::save_library_sort(&path, &sort).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+
+        assert!(saved.starts_with("# My config\nlibrary_root = \"~/music\" # old name\n"));
+        assert!(saved.ends_with("[extra]\nkey = 1\n"));
+
+        #[derive(Deserialize)]
+        struct Saved {
+            library_sort: Vec<SortKey>,
+        }
+        let saved: Saved = toml::from_str(&saved).unwrap();
+        assert_eq!(saved.library_sort, sort);
+    }
+
+    #[test]
+    fn library_roots_with_labels() {
+        let config = Config::from_str(
+            r#"
+            library_roots = ["/music", { path = "/mnt/nas/music", label = "NAS" }]
+            theme = "default"
+            show_track_art = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.library_roots,
+            [
+                LibraryRoot::new("/music".into()),
+                LibraryRoot {
+                    path: "/mnt/nas/music".into(),
+                    label: Some("NAS".to_owned()),
+                },
+            ]
+        );
+        assert_eq!(config.library_roots[0].name(), "music");
+
+        let s = toml::to_string(&config).unwrap();
+        assert_eq!(config, Config::from_str(&s).unwrap());
+    }
+
+    #[test]
+    fn theme_can_be_a_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::from_str(&format!(
+            r#"
+            library_roots = ["{}", "{}"]
+            theme = "~/themes/dark.toml"
+            show_track_art = true
+            "#,
+            dir.path().display(),
+            dir.path().join("missing").display(),
+        ))
+        .unwrap();
+        let theme = dirs::home_dir().unwrap().join("themes/dark.toml");
+        assert_eq!(config.theme_path().unwrap(), theme);
+        assert_eq!(
+            config.missing_paths(),
+            [
+                ("library_roots", dir.path().join("missing")),
+                ("theme", theme)
+            ]
+        );
+
+        config.theme = "default".to_owned();
+        assert!(
+            config
+                .theme_path()
+                .unwrap()
+                .ends_with("themes/default.toml")
+        );
+    }
+
+    #[test]
+    fn library_roots_expand_home() {
+        let config = Config::from_str(include_str!("../assets/config.toml")).unwrap();
+        assert_eq!(
+            config.library_roots,
+            [LibraryRoot::new(dirs::home_dir().unwrap().join("music"))]
+        );
+
+        let error = Config::from_str(
+            r#"
+            library_roots = "$MINIM_UNSET_TEST_VARIABLE/music"
+            theme = "default"
+            show_track_art = true
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("MINIM_UNSET_TEST_VARIABLE isn't set")
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    f32::consts::FRAC_PI_2,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use rodio::{
+    ChannelCount, Sample, SampleRate, Source,
+    source::{Empty, SeekError, UniformSourceIterator},
+};
+
+/// The rest of a track that's fading out
+type Tail = Box<dyn Source + Send>;
+
+#[derive(Clone, Default)]
+/// Where a track leaves its end for the next track to mix in, along with how long to fade for
+pub(crate) struct Handover(Arc<Mutex<Option<(Tail, Duration)>>>);
+
+/// The end of the previous track, mixed into the start of this one
+struct FadeIn {
+    tail: UniformSourceIterator<Tail>,
+    /// Samples mixed in so far
+    position: u64,
+    /// Samples to fade over
+    length: u64,
+}
+
+/// A track that fades into the next one over its last few seconds
+///
+/// Rather than playing both tracks at once, the track ends early and hands the rest of itself
+/// over to the next track, which mixes it in. That way the sink moves on to the next track as
+/// soon as the fade starts, and its position and seeking apply to the track fading in.
+pub(crate) struct Crossfade<F> {
+    source: Tail,
+    /// Falls back to the duration from the tags if the decoder doesn't know
+    duration: Duration,
+    fade_length: Duration,
+    enabled: Arc<AtomicBool>,
+    /// Where the previous track leaves its end
+    handover: Handover,
+    fade_in: Option<FadeIn>,
+    /// Samples played so far, for working out when to start fading out
+    played: u64,
+    /// Set once the fade out has been considered, so it's only attempted once
+    fade_out_checked: bool,
+    /// Returns where to leave the end of this track, or `None` if it shouldn't fade out
+    next_handover: F,
+}
+
+impl<F> Crossfade<F>
+where
+    F: FnMut() -> Option<Handover>,
+{
+    pub fn new(
+        source: impl Source + Send + 'static,
+        duration: Duration,
+        fade_length: Duration,
+        enabled: Arc<AtomicBool>,
+        handover: Handover,
+        next_handover: F,
+    ) -> Self {
+        let duration = source.total_duration().unwrap_or(duration);
+        Self {
+            source: Box::new(source),
+            duration,
+            fade_length,
+            enabled,
+            handover,
+            fade_in: None,
+            played: 0,
+            fade_out_checked: false,
+            next_handover,
+        }
+    }
+
+    fn samples_per_second(&self) -> f64 {
+        f64::from(self.source.sample_rate()) * f64::from(self.source.channels())
+    }
+
+    fn remaining(&self) -> Duration {
+        let elapsed = Duration::from_secs_f64(self.played as f64 / self.samples_per_second());
+        self.duration.saturating_sub(elapsed)
+    }
+
+    /// Hands the rest of the track over to the next one if it's time to fade out
+    ///
+    /// Returns whether the track was handed over, in which case it's done. Tracks whose length
+    /// isn't known play out in full, since there's no telling when their end starts.
+    fn hand_over(&mut self) -> bool {
+        if self.fade_out_checked
+            || !self.enabled.load(Ordering::Relaxed)
+            || self.fade_length.is_zero()
+            || self.duration.is_zero()
+            || self.remaining() > self.fade_length
+        {
+            return false;
+        }
+
+        self.fade_out_checked = true;
+        let Some(handover) = (self.next_handover)() else {
+            return false;
+        };
+
+        let fade_length = self.remaining();
+        let tail = std::mem::replace(&mut self.source, Box::new(Empty::new()));
+        *handover.0.lock().unwrap() = Some((tail, fade_length));
+        true
+    }
+
+    /// Picks up the end of the previous track if it was handed over
+    fn start_fade_in(&mut self) {
+        let Some((tail, fade_length)) = self.handover.0.lock().unwrap().take() else {
+            return;
+        };
+
+        let tail =
+            UniformSourceIterator::new(tail, self.source.channels(), self.source.sample_rate());
+        let length = (fade_length.as_secs_f64() * self.samples_per_second()) as u64;
+        self.fade_in = Some(FadeIn {
+            tail,
+            position: 0,
+            length,
+        });
+    }
+}
+
+impl<F> Iterator for Crossfade<F>
+where
+    F: FnMut() -> Option<Handover>,
+{
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.hand_over() {
+            return None;
+        }
+        if self.played == 0 {
+            self.start_fade_in();
+        }
+
+        let sample = self.source.next()?;
+        self.played += 1;
+
+        let Some(fade_in) = &mut self.fade_in else {
+            return Some(sample);
+        };
+        // Equal power, so the volume doesn't dip in the middle
+        let progress = fade_in.position as f32 / fade_in.length.max(1) as f32;
+        let angle = progress.min(1.0) * FRAC_PI_2;
+        let tail = fade_in.tail.next().unwrap_or_default();
+        fade_in.position += 1;
+        if fade_in.position >= fade_in.length {
+            self.fade_in = None;
+        }
+
+        Some(sample * angle.sin() + tail * angle.cos())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<F> Source for Crossfade<F>
+where
+    F: FnMut() -> Option<Handover>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.source.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    /// Seeking stops any fade in progress, and fades out again when seeking back before the end
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)?;
+        self.played = (pos.as_secs_f64() * self.samples_per_second()) as u64;
+        self.fade_in = None;
+        self.fade_out_checked = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    /// A second of mono audio at 10 samples per second
+    fn second_of(value: Sample) -> SamplesBuffer {
+        SamplesBuffer::new(1, 10, vec![value; 10])
+    }
+
+    #[test]
+    fn end_of_track_is_mixed_into_the_next() {
+        let enabled = Arc::new(AtomicBool::new(true));
+        let handover = Handover::default();
+        let next_handover = handover.clone();
+        let fade_length = Duration::from_millis(500);
+
+        let outgoing = Crossfade::new(
+            second_of(0.5),
+            Duration::ZERO,
+            fade_length,
+            enabled.clone(),
+            Handover::default(),
+            move || Some(next_handover.clone()),
+        );
+        assert_eq!(outgoing.count(), 5);
+
+        let incoming = Crossfade::new(
+            second_of(1.0),
+            Duration::ZERO,
+            fade_length,
+            enabled,
+            handover,
+            || None,
+        );
+        let samples: Vec<_> = incoming.collect();
+        assert_eq!(samples.len(), 10);
+        assert_eq!(samples[0], 0.5);
+        assert!(samples[1..5].iter().all(|sample| *sample > 0.5));
+        assert_eq!(samples[5..], [1.0; 5]);
+    }
+
+    #[test]
+    fn tracks_play_out_without_a_handover() {
+        let disabled = Crossfade::new(
+            second_of(0.5),
+            Duration::ZERO,
+            Duration::from_millis(500),
+            Arc::new(AtomicBool::new(false)),
+            Handover::default(),
+            || Some(Handover::default()),
+        );
+        assert_eq!(disabled.count(), 10);
+
+        let same_album = Crossfade::new(
+            second_of(0.5),
+            Duration::ZERO,
+            Duration::from_millis(500),
+            Arc::new(AtomicBool::new(true)),
+            Handover::default(),
+            || None,
+        );
+        assert_eq!(same_album.count(), 10);
+    }
+
+    #[test]
+    fn tracks_of_unknown_length_play_out() {
+        let source = rodio::source::from_iter(std::iter::once(second_of(0.5)));
+        assert_eq!(source.total_duration(), None);
+
+        let unknown = Crossfade::new(
+            source,
+            Duration::ZERO,
+            Duration::from_millis(500),
+            Arc::new(AtomicBool::new(true)),
+            Handover::default(),
+            || Some(Handover::default()),
+        );
+        assert_eq!(unknown.count(), 10);
+    }
+}
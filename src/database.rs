@@ -0,0 +1,1306 @@
+use std::{
+    collections::HashMap,
+    path::{MAIN_SEPARATOR, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::{Result, eyre::eyre};
+use rusqlite::{Connection, OptionalExtension, Params, Row, params, params_from_iter};
+
+use crate::track::{
+    CachedField, Collation, SortDirection, SortKey, Track, TrackFilter, field_list,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// How often a track has been played, and how it's rated
+pub(crate) struct PlayStats {
+    pub plays: u32,
+    /// Out of 10
+    pub rating: Option<u8>,
+}
+
+/// Version of the schema created by [`SCHEMA`], stored in SQLite's `user_version`
+const SCHEMA_VERSION: i32 = 4;
+
+/// Brings the schema from each version to the next, starting from version 1
+const MIGRATIONS: &[&str] = &[
+    "
+    ALTER TABLE tracks ADD COLUMN root TEXT;
+    CREATE INDEX tracks_root ON tracks (root);
+    ",
+    "ALTER TABLE tracks ADD COLUMN inferred TEXT NOT NULL DEFAULT '';",
+    // Sort keys are filled in by `LibraryDatabase::update_sort_keys` afterwards
+    "
+    ALTER TABLE tracks ADD COLUMN title_key TEXT;
+    ALTER TABLE tracks ADD COLUMN artist_key TEXT;
+    ALTER TABLE tracks ADD COLUMN album_key TEXT;
+    ALTER TABLE tracks ADD COLUMN album_artist_key TEXT;
+    ALTER TABLE tracks ADD COLUMN genre_key TEXT;
+    ALTER TABLE tracks ADD COLUMN codec_key TEXT;
+    ALTER TABLE tracks ADD COLUMN root_key TEXT;
+    DROP INDEX tracks_title;
+    DROP INDEX tracks_artist;
+    DROP INDEX tracks_album;
+    DROP INDEX tracks_album_artist;
+    DROP INDEX tracks_disc_number;
+    DROP INDEX tracks_disc_total;
+    DROP INDEX tracks_track_number;
+    DROP INDEX tracks_track_total;
+    DROP INDEX tracks_year;
+    DROP INDEX tracks_genre;
+    DROP INDEX tracks_duration;
+    DROP INDEX tracks_codec;
+    DROP INDEX tracks_bitrate;
+    DROP INDEX tracks_sample_rate;
+    DROP INDEX tracks_bit_depth;
+    DROP INDEX tracks_channels;
+    CREATE INDEX tracks_title_key ON tracks (title_key, path);
+    CREATE INDEX tracks_artist_key ON tracks (artist_key, path);
+    CREATE INDEX tracks_album_key ON tracks (album_key, path);
+    CREATE INDEX tracks_album_artist_key ON tracks (album_artist_key, path);
+    CREATE INDEX tracks_disc_number ON tracks (disc_number, path);
+    CREATE INDEX tracks_disc_total ON tracks (disc_total, path);
+    CREATE INDEX tracks_track_number ON tracks (track_number, path);
+    CREATE INDEX tracks_track_total ON tracks (track_total, path);
+    CREATE INDEX tracks_year ON tracks (year, path);
+    CREATE INDEX tracks_genre_key ON tracks (genre_key, path);
+    CREATE INDEX tracks_duration ON tracks (duration_ms, path);
+    CREATE INDEX tracks_codec_key ON tracks (codec_key, path);
+    CREATE INDEX tracks_bitrate ON tracks (bitrate, path);
+    CREATE INDEX tracks_sample_rate ON tracks (sample_rate, path);
+    CREATE INDEX tracks_bit_depth ON tracks (bit_depth, path);
+    CREATE INDEX tracks_channels ON tracks (channels, path);
+    CREATE INDEX tracks_root_key ON tracks (root_key, path);
+
+    ALTER TABLE track_artists ADD COLUMN artist_key TEXT NOT NULL DEFAULT '';
+    DROP INDEX track_artists_artist;
+    CREATE INDEX track_artists_artist_key ON track_artists (artist_key);
+
+    CREATE TABLE settings (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    ",
+];
+
+/// Text fields that are sorted by a key stored in their own column, from
+/// [`Track::stored_sort_key`]
+///
+/// Comparing text with the [`Collation`] in queries would keep SQLite from using the indexes.
+const SORT_KEY_COLUMNS: [(CachedField, &str); 7] = [
+    (CachedField::Title, "title_key"),
+    (CachedField::Artist, "artist_key"),
+    (CachedField::Album, "album_key"),
+    (CachedField::AlbumArtist, "album_artist_key"),
+    (CachedField::Genre, "genre_key"),
+    (CachedField::Codec, "codec_key"),
+    (CachedField::Root, "root_key"),
+];
+
+/// Only keeps tracks from the library root labelled `?1`, unless it's `NULL`
+const ROOT_FILTER: &str = "WHERE (?1 IS NULL OR root = ?1)";
+
+/// Separates the artists of a track when they're read in the same row
+const ARTIST_SEPARATOR: char = '\u{1f}';
+
+const SCHEMA: &str = "
+    CREATE TABLE tracks (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        title TEXT,
+        -- The first artist, for sorting. Every artist is in `track_artists`.
+        artist TEXT,
+        album TEXT,
+        album_artist TEXT,
+        disc_number INTEGER,
+        disc_total INTEGER,
+        track_number INTEGER,
+        track_total INTEGER,
+        year INTEGER,
+        genre TEXT,
+        title_sort TEXT,
+        artist_sort TEXT,
+        album_sort TEXT,
+        album_artist_sort TEXT,
+        duration_ms INTEGER NOT NULL,
+        codec TEXT,
+        bitrate INTEGER,
+        sample_rate INTEGER,
+        bit_depth INTEGER,
+        channels INTEGER,
+        file_modified INTEGER NOT NULL,
+        file_size INTEGER NOT NULL,
+        -- Label of the library root the track was found in
+        root TEXT,
+        -- Space-separated fields that were filled in from the path, rather than tags
+        inferred TEXT NOT NULL DEFAULT '',
+        -- What text fields are sorted by, see `SORT_KEY_COLUMNS`
+        title_key TEXT,
+        artist_key TEXT,
+        album_key TEXT,
+        album_artist_key TEXT,
+        genre_key TEXT,
+        codec_key TEXT,
+        root_key TEXT
+    );
+    -- Ties are broken by path, so sorting by a single field can be read from its index
+    CREATE INDEX tracks_title_key ON tracks (title_key, path);
+    CREATE INDEX tracks_artist_key ON tracks (artist_key, path);
+    CREATE INDEX tracks_album_key ON tracks (album_key, path);
+    CREATE INDEX tracks_album_artist_key ON tracks (album_artist_key, path);
+    CREATE INDEX tracks_disc_number ON tracks (disc_number, path);
+    CREATE INDEX tracks_disc_total ON tracks (disc_total, path);
+    CREATE INDEX tracks_track_number ON tracks (track_number, path);
+    CREATE INDEX tracks_track_total ON tracks (track_total, path);
+    CREATE INDEX tracks_year ON tracks (year, path);
+    CREATE INDEX tracks_genre_key ON tracks (genre_key, path);
+    CREATE INDEX tracks_duration ON tracks (duration_ms, path);
+    CREATE INDEX tracks_codec_key ON tracks (codec_key, path);
+    CREATE INDEX tracks_bitrate ON tracks (bitrate, path);
+    CREATE INDEX tracks_sample_rate ON tracks (sample_rate, path);
+    CREATE INDEX tracks_bit_depth ON tracks (bit_depth, path);
+    CREATE INDEX tracks_channels ON tracks (channels, path);
+    CREATE INDEX tracks_root ON tracks (root);
+    CREATE INDEX tracks_root_key ON tracks (root_key, path);
+
+    CREATE TABLE track_artists (
+        track_id INTEGER NOT NULL REFERENCES tracks (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        artist TEXT NOT NULL,
+        -- The artist's collation key, so spellings that sort the same are browsed together
+        artist_key TEXT NOT NULL DEFAULT '',
+        PRIMARY KEY (track_id, position)
+    );
+    CREATE INDEX track_artists_artist_key ON track_artists (artist_key);
+
+    CREATE TABLE plays (
+        id INTEGER PRIMARY KEY,
+        track_id INTEGER NOT NULL REFERENCES tracks (id) ON DELETE CASCADE,
+        -- Milliseconds since the Unix epoch
+        played_at INTEGER NOT NULL
+    );
+    CREATE INDEX plays_track ON plays (track_id);
+
+    CREATE TABLE ratings (
+        track_id INTEGER PRIMARY KEY REFERENCES tracks (id) ON DELETE CASCADE,
+        -- Out of 10, so half stars can be shown
+        rating INTEGER NOT NULL CHECK (rating BETWEEN 0 AND 10)
+    );
+
+    CREATE TABLE playlists (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE playlist_tracks (
+        playlist_id INTEGER NOT NULL REFERENCES playlists (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        track_id INTEGER NOT NULL REFERENCES tracks (id) ON DELETE CASCADE,
+        PRIMARY KEY (playlist_id, position)
+    );
+
+    -- What the stored sort keys were made with, see `LibraryDatabase::update_sort_keys`
+    CREATE TABLE settings (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+/// Every column of a [`Track`], with its artists last
+const TRACK_COLUMNS: &str = "id, path, title, album, album_artist, disc_number, disc_total, \
+    track_number, track_total, year, genre, title_sort, artist_sort, album_sort, \
+    album_artist_sort, duration_ms, codec, bitrate, sample_rate, bit_depth, channels, \
+    file_modified, file_size, root, inferred, \
+    (SELECT group_concat(artist, char(31) ORDER BY position) FROM track_artists \
+        WHERE track_id = tracks.id)";
+
+/// Inserts or updates a track, unless nothing about it has changed
+///
+/// `?33` is the track's artists, joined with [`ARTIST_SEPARATOR`].
+const UPSERT_TRACK: &str = "
+    INSERT INTO tracks (path, title, artist, album, album_artist, disc_number, disc_total,
+        track_number, track_total, year, genre, title_sort, artist_sort, album_sort,
+        album_artist_sort, duration_ms, codec, bitrate, sample_rate, bit_depth, channels,
+        file_modified, file_size, root, inferred, title_key, artist_key, album_key,
+        album_artist_key, genre_key, codec_key, root_key)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+        ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32)
+    ON CONFLICT (path) DO UPDATE SET
+        title = excluded.title,
+        artist = excluded.artist,
+        album = excluded.album,
+        album_artist = excluded.album_artist,
+        disc_number = excluded.disc_number,
+        disc_total = excluded.disc_total,
+        track_number = excluded.track_number,
+        track_total = excluded.track_total,
+        year = excluded.year,
+        genre = excluded.genre,
+        title_sort = excluded.title_sort,
+        artist_sort = excluded.artist_sort,
+        album_sort = excluded.album_sort,
+        album_artist_sort = excluded.album_artist_sort,
+        duration_ms = excluded.duration_ms,
+        codec = excluded.codec,
+        bitrate = excluded.bitrate,
+        sample_rate = excluded.sample_rate,
+        bit_depth = excluded.bit_depth,
+        channels = excluded.channels,
+        file_modified = excluded.file_modified,
+        file_size = excluded.file_size,
+        root = excluded.root,
+        inferred = excluded.inferred,
+        title_key = excluded.title_key,
+        artist_key = excluded.artist_key,
+        album_key = excluded.album_key,
+        album_artist_key = excluded.album_artist_key,
+        genre_key = excluded.genre_key,
+        codec_key = excluded.codec_key,
+        root_key = excluded.root_key
+    -- Tags can be edited without changing the file's modification time or size, so every
+    -- column is compared
+    WHERE (title, artist, album, album_artist, disc_number, disc_total, track_number,
+            track_total, year, genre, title_sort, artist_sort, album_sort, album_artist_sort,
+            duration_ms, codec, bitrate, sample_rate, bit_depth, channels, file_modified,
+            file_size, root, inferred, title_key, artist_key, album_key, album_artist_key,
+            genre_key, codec_key, root_key)
+        IS NOT (excluded.title, excluded.artist, excluded.album, excluded.album_artist,
+            excluded.disc_number, excluded.disc_total, excluded.track_number,
+            excluded.track_total, excluded.year, excluded.genre, excluded.title_sort,
+            excluded.artist_sort, excluded.album_sort, excluded.album_artist_sort,
+            excluded.duration_ms, excluded.codec, excluded.bitrate, excluded.sample_rate,
+            excluded.bit_depth, excluded.channels, excluded.file_modified, excluded.file_size,
+            excluded.root, excluded.inferred, excluded.title_key, excluded.artist_key,
+            excluded.album_key, excluded.album_artist_key, excluded.genre_key,
+            excluded.codec_key, excluded.root_key)
+        OR (SELECT group_concat(artist, char(31) ORDER BY position) FROM track_artists
+            WHERE track_id = tracks.id) IS NOT ?33
+    RETURNING id
+";
+
+/// Library store backed by an SQLite database
+///
+/// Unlike the CSV cache, only tracks that have changed are written, and the library view reads
+/// it a page at a time in the order it's sorted in, using the indexes. It also holds play
+/// history, ratings and playlists.
+pub(crate) struct LibraryDatabase {
+    connection: Connection,
+    collation: Collation,
+}
+
+impl LibraryDatabase {
+    /// Opens or creates the database, comparing text with the given [`Collation`]
+    pub fn open(path: &Path, collation: Collation) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        let database = Self {
+            connection,
+            collation,
+        };
+        database.migrate()?;
+        database.update_sort_keys()?;
+
+        Ok(database)
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let version: i32 = self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+        match version {
+            0 => {
+                self.connection.execute_batch(SCHEMA)?;
+                self.connection
+                    .pragma_update(None, "user_version", SCHEMA_VERSION)?;
+            }
+            1..SCHEMA_VERSION => {
+                let transaction = self.connection.unchecked_transaction()?;
+                for migration in &MIGRATIONS[version as usize - 1..] {
+                    transaction.execute_batch(migration)?;
+                }
+                transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+                transaction.commit()?;
+            }
+            SCHEMA_VERSION => {}
+            _ => return Err(eyre!("Unsupported library database version {version}")),
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes the stored sort keys if they were made with a different [`Collation`], e.g.,
+    /// after `ignored_articles` was changed in the config
+    fn update_sort_keys(&self) -> Result<()> {
+        let collation = self.collation.ignored_articles.join("\n");
+        let stored: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT value FROM settings WHERE name = 'collation'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if stored.as_ref() == Some(&collation) {
+            return Ok(());
+        }
+
+        let tracks = self.query_tracks("", [])?;
+        let transaction = self.connection.unchecked_transaction()?;
+        {
+            let mut update = transaction.prepare(
+                "UPDATE tracks SET title_key = ?2, artist_key = ?3, album_key = ?4, \
+                    album_artist_key = ?5, genre_key = ?6, codec_key = ?7, root_key = ?8 \
+                    WHERE path = ?1",
+            )?;
+            let mut update_artist = transaction.prepare(
+                "UPDATE track_artists SET artist_key = ?3 \
+                    WHERE track_id = (SELECT id FROM tracks WHERE path = ?1) AND position = ?2",
+            )?;
+            for track in &tracks {
+                let [title, artist, album, album_artist, genre, codec, root] =
+                    Self::sort_keys(track, &self.collation);
+                update.execute(params![
+                    track.path.to_string_lossy(),
+                    title,
+                    artist,
+                    album,
+                    album_artist,
+                    genre,
+                    codec,
+                    root,
+                ])?;
+                for (position, artist) in track.artists.iter().enumerate() {
+                    update_artist.execute(params![
+                        track.path.to_string_lossy(),
+                        position,
+                        self.collation.key(artist)
+                    ])?;
+                }
+            }
+        }
+        transaction.execute(
+            "INSERT INTO settings (name, value) VALUES ('collation', ?1) \
+                ON CONFLICT (name) DO UPDATE SET value = excluded.value",
+            [&collation],
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// The values stored in the [`SORT_KEY_COLUMNS`] for a track
+    fn sort_keys(track: &Track, collation: &Collation) -> [Option<String>; SORT_KEY_COLUMNS.len()] {
+        SORT_KEY_COLUMNS.map(|(field, _)| track.stored_sort_key(&field, collation))
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self
+            .connection
+            .query_row("SELECT NOT EXISTS (SELECT 1 FROM tracks)", [], |row| {
+                row.get(0)
+            })?)
+    }
+
+    /// Makes the database hold exactly the given tracks
+    ///
+    /// Only tracks that have changed are written. Tracks keep their play history, ratings and
+    /// places in playlists as long as they stay in the library.
+    pub fn sync_tracks(&mut self, tracks: &[Track]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS seen (path TEXT PRIMARY KEY); DELETE FROM seen;",
+        )?;
+
+        {
+            let mut insert_seen = transaction.prepare("INSERT OR IGNORE INTO seen VALUES (?1)")?;
+            for track in tracks {
+                insert_seen.execute([track.path.to_string_lossy()])?;
+            }
+        }
+        Self::write_tracks(&transaction, &self.collation, tracks)?;
+
+        transaction.execute(
+            "DELETE FROM tracks WHERE path NOT IN (SELECT path FROM seen)",
+            [],
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Adds the given tracks, or updates them if they're already in the database
+    ///
+    /// Only tracks that have changed are written, and every other track is left alone.
+    pub fn upsert_tracks(&mut self, tracks: &[Track]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        Self::write_tracks(&transaction, &self.collation, tracks)?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Removes the tracks at the given paths, along with their play history and ratings
+    pub fn remove_tracks(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut remove = transaction.prepare("DELETE FROM tracks WHERE path = ?1")?;
+            for path in paths {
+                remove.execute([path.to_string_lossy()])?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Inserts or updates each track that has changed, and its artists
+    fn write_tracks(
+        connection: &Connection,
+        collation: &Collation,
+        tracks: &[Track],
+    ) -> Result<()> {
+        let mut upsert = connection.prepare(UPSERT_TRACK)?;
+        let mut remove_artists =
+            connection.prepare("DELETE FROM track_artists WHERE track_id = ?1")?;
+        let mut insert_artist = connection.prepare(
+            "INSERT INTO track_artists (track_id, position, artist, artist_key) \
+                VALUES (?1, ?2, ?3, ?4)",
+        )?;
+
+        for track in tracks {
+            let [
+                title_key,
+                artist_key,
+                album_key,
+                album_artist_key,
+                genre_key,
+                codec_key,
+                root_key,
+            ] = Self::sort_keys(track, collation);
+            let artists = (!track.artists.is_empty())
+                .then(|| track.artists.join(&ARTIST_SEPARATOR.to_string()));
+
+            let id: Option<i64> = upsert
+                .query_row(
+                    params![
+                        track.path.to_string_lossy(),
+                        track.title,
+                        track.artists.first(),
+                        track.album,
+                        track.album_artist,
+                        track.disc_number,
+                        track.disc_total,
+                        track.track_number,
+                        track.track_total,
+                        track.year,
+                        track.genre,
+                        track.title_sort,
+                        track.artist_sort,
+                        track.album_sort,
+                        track.album_artist_sort,
+                        i64::try_from(track.duration.as_millis()).unwrap_or(i64::MAX),
+                        track.codec,
+                        track.bitrate,
+                        track.sample_rate,
+                        track.bit_depth,
+                        track.channels,
+                        track.file_modified,
+                        track.file_size,
+                        track.root,
+                        field_list::to_string(&track.inferred),
+                        title_key,
+                        artist_key,
+                        album_key,
+                        album_artist_key,
+                        genre_key,
+                        codec_key,
+                        root_key,
+                        artists,
+                    ],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            // Nothing was returned if the track was already up to date
+            if let Some(id) = id {
+                remove_artists.execute([id])?;
+                for (position, artist) in track.artists.iter().enumerate() {
+                    insert_artist.execute(params![id, position, artist, collation.key(artist)])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every track, in the given order
+    pub fn tracks(&self, sort: &[SortKey]) -> Result<Vec<Track>> {
+        self.query_tracks(&format!("ORDER BY {}", Self::order_by(sort)), [])
+    }
+
+    /// Number of tracks, only counting those from the library root labelled `root` if given
+    pub fn track_count(&self, root: Option<&str>) -> Result<usize> {
+        Ok(self.connection.query_row(
+            &format!("SELECT COUNT(*) FROM tracks {ROOT_FILTER}"),
+            [root],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Up to `limit` tracks, starting from the one at `offset` in the given order
+    ///
+    /// Only tracks from the library root labelled `root` are included if it's given.
+    pub fn tracks_page(
+        &self,
+        sort: &[SortKey],
+        root: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Track>> {
+        self.query_tracks(
+            &format!(
+                "{ROOT_FILTER} ORDER BY {} LIMIT ?2 OFFSET ?3",
+                Self::order_by(sort)
+            ),
+            params![root, limit, offset],
+        )
+    }
+
+    /// Where the track at `path` is in [`LibraryDatabase::tracks_page`]'s order
+    pub fn track_position(
+        &self,
+        path: &Path,
+        sort: &[SortKey],
+        root: Option<&str>,
+    ) -> Result<Option<usize>> {
+        Ok(self
+            .connection
+            .query_row(
+                &format!(
+                    "SELECT position FROM (SELECT path, \
+                        row_number() OVER (ORDER BY {}) - 1 AS position \
+                        FROM tracks {ROOT_FILTER}) \
+                        WHERE path = ?2",
+                    Self::order_by(sort)
+                ),
+                params![root, path.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Tracks by the given artist, compared the same way as when sorting
+    pub fn tracks_by_artist(&self, artist: &str, sort: &[SortKey]) -> Result<Vec<Track>> {
+        self.query_tracks(
+            &format!(
+                "WHERE id IN (SELECT track_id FROM track_artists WHERE artist_key = ?1) \
+                    ORDER BY {}",
+                Self::order_by(sort)
+            ),
+            [self.collation.key(artist)],
+        )
+    }
+
+    /// Tracks on the same album as `track`, see [`Track::same_album`]
+    ///
+    /// SQLite only ignores the case of ASCII letters, so tracks whose album names differ in the
+    /// case of other letters aren't found.
+    pub fn album_tracks(&self, track: &Track, sort: &[SortKey]) -> Result<Vec<Track>> {
+        let Some(album) = &track.album else {
+            return self.query_tracks("WHERE path = ?1", [track.path.to_string_lossy()]);
+        };
+
+        let tracks = self.query_tracks(
+            &format!(
+                "WHERE album = ?1 COLLATE NOCASE ORDER BY {}",
+                Self::order_by(sort)
+            ),
+            [album],
+        )?;
+        Ok(tracks.into_iter().filter(|t| t.same_album(track)).collect())
+    }
+
+    /// Tracks at the given paths, or in the directories at them
+    pub fn tracks_under(&self, paths: &[PathBuf]) -> Result<Vec<Track>> {
+        // Paths inside a directory start with it and a separator, and sort before it followed
+        // by the character after the separator
+        let after_separator = char::from_u32(MAIN_SEPARATOR as u32 + 1).unwrap_or(char::MAX);
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {TRACK_COLUMNS} FROM tracks \
+                WHERE path = ?1 OR (path >= ?1 || ?2 AND path < ?1 || ?3)"
+        ))?;
+
+        let mut tracks = Vec::new();
+        for path in paths {
+            let rows = statement.query_map(
+                params![
+                    path.to_string_lossy(),
+                    MAIN_SEPARATOR.to_string(),
+                    after_separator.to_string()
+                ],
+                Self::track_from_row,
+            )?;
+            for track in rows {
+                tracks.push(track?);
+            }
+        }
+        tracks.sort_by(|a, b| a.path.cmp(&b.path));
+        tracks.dedup_by(|a, b| a.path == b.path);
+
+        Ok(tracks)
+    }
+
+    /// Tracks matching a search, in the given order
+    ///
+    /// Every word has to be in the title, an artist, the album, the album artist or the genre,
+    /// ignoring the case of ASCII letters, and the track has to satisfy every filter.
+    pub fn search(
+        &self,
+        words: &[String],
+        filters: &[TrackFilter],
+        sort: &[SortKey],
+    ) -> Result<Vec<Track>> {
+        let conditions: Vec<String> = (1..=words.len())
+            .map(|i| {
+                format!(
+                    "(title LIKE ?{i} ESCAPE '\\' OR album LIKE ?{i} ESCAPE '\\' \
+                        OR album_artist LIKE ?{i} ESCAPE '\\' OR genre LIKE ?{i} ESCAPE '\\' \
+                        OR EXISTS (SELECT 1 FROM track_artists WHERE track_id = tracks.id \
+                            AND artist LIKE ?{i} ESCAPE '\\'))"
+                )
+            })
+            .collect();
+        let clauses = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let patterns = words.iter().map(|word| {
+            let word = word
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{word}%")
+        });
+
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {TRACK_COLUMNS} FROM tracks {clauses} ORDER BY {}",
+            Self::order_by(sort)
+        ))?;
+        let mut tracks = Vec::new();
+        for track in statement.query_map(params_from_iter(patterns), Self::track_from_row)? {
+            let track = track?;
+            if filters.iter().all(|filter| filter.matches(&track)) {
+                tracks.push(track);
+            }
+        }
+
+        Ok(tracks)
+    }
+
+    /// Every artist, sorted, with each one only listed under a single spelling
+    pub fn artists(&self) -> Result<Vec<String>> {
+        let mut statement = self.connection.prepare(
+            "SELECT MIN(artist) FROM track_artists GROUP BY artist_key ORDER BY artist_key",
+        )?;
+        let artists = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(artists)
+    }
+
+    /// Play counts and ratings, keyed by path
+    pub fn play_stats(&self) -> Result<HashMap<PathBuf, PlayStats>> {
+        let mut statement = self.connection.prepare(
+            "SELECT path, \
+                (SELECT COUNT(*) FROM plays WHERE track_id = tracks.id), \
+                (SELECT rating FROM ratings WHERE track_id = tracks.id) \
+                FROM tracks",
+        )?;
+        let stats = statement
+            .query_map([], |row| {
+                let path: String = row.get(0)?;
+                let stats = PlayStats {
+                    plays: row.get(1)?,
+                    rating: row.get(2)?,
+                };
+                Ok((PathBuf::from(path), stats))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(stats)
+    }
+
+    /// Adds a play of the track at `path` to the play history
+    pub fn record_play(&self, path: &Path, played_at: SystemTime) -> Result<()> {
+        let played_at = played_at.duration_since(UNIX_EPOCH).map_or(0, |time| {
+            i64::try_from(time.as_millis()).unwrap_or(i64::MAX)
+        });
+        self.connection.execute(
+            "INSERT INTO plays (track_id, played_at) SELECT id, ?2 FROM tracks WHERE path = ?1",
+            params![path.to_string_lossy(), played_at],
+        )?;
+
+        Ok(())
+    }
+
+    /// Rates the track at `path` out of 10, or removes its rating
+    pub fn set_rating(&self, path: &Path, rating: Option<u8>) -> Result<()> {
+        let path = path.to_string_lossy();
+        match rating {
+            Some(rating) => self.connection.execute(
+                "INSERT INTO ratings (track_id, rating) SELECT id, ?2 FROM tracks WHERE path = ?1 \
+                    ON CONFLICT (track_id) DO UPDATE SET rating = excluded.rating",
+                params![path, rating],
+            )?,
+            None => self.connection.execute(
+                "DELETE FROM ratings WHERE track_id = (SELECT id FROM tracks WHERE path = ?1)",
+                [path],
+            )?,
+        };
+
+        Ok(())
+    }
+
+    /// The rating of the track at `path`, out of 10
+    pub fn rating(&self, path: &Path) -> Result<Option<u8>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT rating FROM ratings JOIN tracks ON tracks.id = track_id WHERE path = ?1",
+                [path.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Names of the saved playlists, sorted
+    pub fn playlists(&self) -> Result<Vec<String>> {
+        let mut statement = self.connection.prepare("SELECT name FROM playlists")?;
+        let mut names: Vec<String> = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        names.sort_by_cached_key(|name| (self.collation.key(name), name.clone()));
+
+        Ok(names)
+    }
+
+    /// Saves the tracks as a playlist, replacing any playlist with the same name
+    ///
+    /// Tracks that aren't in the library are left out.
+    pub fn save_playlist(&mut self, name: &str, tracks: &[Track]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        let id: i64 = transaction.query_row(
+            "INSERT INTO playlists (name) VALUES (?1) \
+                ON CONFLICT (name) DO UPDATE SET name = excluded.name RETURNING id",
+            [name],
+            |row| row.get(0),
+        )?;
+        transaction.execute("DELETE FROM playlist_tracks WHERE playlist_id = ?1", [id])?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO playlist_tracks (playlist_id, position, track_id) \
+                    SELECT ?1, ?2, id FROM tracks WHERE path = ?3",
+            )?;
+            for (position, track) in tracks.iter().enumerate() {
+                insert.execute(params![id, position, track.path.to_string_lossy()])?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// The tracks in a playlist, in order
+    pub fn playlist_tracks(&self, name: &str) -> Result<Vec<Track>> {
+        self.query_tracks(
+            "JOIN (SELECT track_id, position AS playlist_position FROM playlist_tracks \
+                WHERE playlist_id = (SELECT id FROM playlists WHERE name = ?1)) \
+                ON track_id = id ORDER BY playlist_position",
+            [name],
+        )
+    }
+
+    pub fn delete_playlist(&self, name: &str) -> Result<()> {
+        self.connection
+            .execute("DELETE FROM playlists WHERE name = ?1", [name])?;
+
+        Ok(())
+    }
+
+    /// Reads the tracks selected by `clauses`, which follow `FROM tracks`
+    fn query_tracks(&self, clauses: &str, params: impl Params) -> Result<Vec<Track>> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("SELECT {TRACK_COLUMNS} FROM tracks {clauses}"))?;
+        let tracks = statement
+            .query_map(params, Self::track_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(tracks)
+    }
+
+    fn track_from_row(row: &Row) -> rusqlite::Result<Track> {
+        Ok(Track {
+            path: PathBuf::from(row.get::<_, String>(1)?),
+            title: row.get(2)?,
+            artists: row
+                .get::<_, Option<String>>(25)?
+                .map(|artists| artists.split(ARTIST_SEPARATOR).map(str::to_owned).collect())
+                .unwrap_or_default(),
+            album: row.get(3)?,
+            album_artist: row.get(4)?,
+            disc_number: row.get(5)?,
+            disc_total: row.get(6)?,
+            track_number: row.get(7)?,
+            track_total: row.get(8)?,
+            year: row.get(9)?,
+            genre: row.get(10)?,
+            title_sort: row.get(11)?,
+            artist_sort: row.get(12)?,
+            album_sort: row.get(13)?,
+            album_artist_sort: row.get(14)?,
+            duration: Duration::from_millis(row.get(15)?),
+            codec: row.get(16)?,
+            bitrate: row.get(17)?,
+            sample_rate: row.get(18)?,
+            bit_depth: row.get(19)?,
+            channels: row.get(20)?,
+            file_modified: row.get(21)?,
+            file_size: row.get(22)?,
+            root: row.get(23)?,
+            inferred: field_list::from_str(&row.get::<_, String>(24)?),
+        })
+    }
+
+    /// `ORDER BY` clause matching [`Track::compare_by_sort_keys`]
+    fn order_by(sort: &[SortKey]) -> String {
+        let mut terms: Vec<String> = sort
+            .iter()
+            .map(|key| {
+                let direction = match key.direction {
+                    SortDirection::Ascending => "ASC",
+                    SortDirection::Descending => "DESC",
+                };
+                format!("{} {direction}", Self::sort_expression(&key.field))
+            })
+            .collect();
+        // Keep the order stable for tracks that compare equal, in the same direction as the
+        // last key so the whole order can be read from a single index
+        let direction = match sort.last().map(|key| key.direction) {
+            Some(SortDirection::Descending) => "DESC",
+            _ => "ASC",
+        };
+        terms.push(format!("path {direction}"));
+
+        terms.join(", ")
+    }
+
+    fn sort_expression(field: &CachedField) -> &'static str {
+        match field {
+            // See `SORT_KEY_COLUMNS`
+            CachedField::Title => "title_key",
+            CachedField::Artist => "artist_key",
+            CachedField::Album => "album_key",
+            CachedField::AlbumArtist => "album_artist_key",
+            CachedField::Genre => "genre_key",
+            CachedField::Codec => "codec_key",
+            CachedField::Root => "root_key",
+            CachedField::DiscNumber => "disc_number",
+            CachedField::DiscTotal => "disc_total",
+            CachedField::TrackNumber => "track_number",
+            CachedField::TrackTotal => "track_total",
+            CachedField::Year => "year",
+            CachedField::Duration => "duration_ms",
+            CachedField::Bitrate => "bitrate",
+            CachedField::SampleRate => "sample_rate",
+            CachedField::BitDepth => "bit_depth",
+            CachedField::Channels => "channels",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn track(path: &str, artists: &[&str], year: u32) -> Track {
+        Track {
+            path: PathBuf::from(path),
+            title: Some(path.to_owned()),
+            artists: artists.iter().map(|a| a.to_string()).collect(),
+            year: Some(year),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sync_sort_and_browse() {
+        let collation = Collation {
+            ignored_articles: vec!["The".to_owned()],
+        };
+        let mut database = LibraryDatabase::open(Path::new(":memory:"), collation).unwrap();
+        assert!(database.is_empty().unwrap());
+
+        let tracks = [
+            track("a", &["The Beatles"], 1965),
+            track("b", &["beatles", "Billy Preston"], 1969),
+            track("c", &["Abba"], 1976),
+        ];
+        database.sync_tracks(&tracks).unwrap();
+
+        let sorted = database
+            .tracks(&[SortKey::descending(CachedField::Year)])
+            .unwrap();
+        let paths: Vec<_> = sorted.iter().map(|t| t.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["c", "b", "a"]);
+        assert_eq!(sorted[1].artists, ["beatles", "Billy Preston"]);
+
+        assert_eq!(
+            database.artists().unwrap(),
+            ["Abba", "The Beatles", "Billy Preston"]
+        );
+        let beatles = database
+            .tracks_by_artist("The Beatles", &SortKey::album_order())
+            .unwrap();
+        assert_eq!(beatles.len(), 2);
+
+        database.sync_tracks(&tracks[..1]).unwrap();
+        assert_eq!(database.tracks(&[]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn play_stats_count_plays_and_ratings() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        database
+            .sync_tracks(&[track("a", &["Abba"], 1976), track("b", &["Abba"], 1976)])
+            .unwrap();
+        database
+            .record_play(Path::new("a"), SystemTime::now())
+            .unwrap();
+        database
+            .record_play(Path::new("a"), SystemTime::now())
+            .unwrap();
+        database.set_rating(Path::new("b"), Some(8)).unwrap();
+
+        let stats = database.play_stats().unwrap();
+        assert_eq!(
+            stats[Path::new("a")],
+            PlayStats {
+                plays: 2,
+                rating: None
+            }
+        );
+        assert_eq!(
+            stats[Path::new("b")],
+            PlayStats {
+                plays: 0,
+                rating: Some(8)
+            }
+        );
+
+        database.set_rating(Path::new("b"), None).unwrap();
+        assert_eq!(database.rating(Path::new("b")).unwrap(), None);
+    }
+
+    #[test]
+    fn tag_edits_are_written_without_file_changes() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        let mut tracks = [track("a", &["Abba"], 1976)];
+        database.sync_tracks(&tracks).unwrap();
+
+        // Same modification time and size, as when tags are edited in the player
+        tracks[0].title = Some("Waterloo".to_owned());
+        tracks[0].artists.push("Benny".to_owned());
+        database.sync_tracks(&tracks).unwrap();
+
+        let stored = database.tracks(&[]).unwrap();
+        assert_eq!(stored[0].title.as_deref(), Some("Waterloo"));
+        assert_eq!(stored[0].artists, ["Abba", "Benny"]);
+        assert_eq!(database.artists().unwrap(), ["Abba", "Benny"]);
+    }
+
+    #[test]
+    fn sorting_matches_sorting_in_memory() {
+        let collation = Collation {
+            ignored_articles: vec!["The".to_owned()],
+        };
+        let mut database = LibraryDatabase::open(Path::new(":memory:"), collation.clone()).unwrap();
+
+        let mut tracks = vec![
+            track("/music/b.flac", &["The Beatles"], 1965),
+            track("/music/a.flac", &["beatles"], 1969),
+            track("/music/c.mp3", &["Abba"], 1976),
+            track("/music/d.mp3", &[], 1976),
+        ];
+        tracks[0].album = Some("Help!".to_owned());
+        tracks[1].title = None;
+        tracks[2].album_artist = Some("ABBA".to_owned());
+        tracks[2].title_sort = Some("Zzz".to_owned());
+        tracks[3].codec = Some("MPEG-1 Layer 3".to_owned());
+        database.sync_tracks(&tracks).unwrap();
+
+        for field in [
+            CachedField::Title,
+            CachedField::Artist,
+            CachedField::Album,
+            CachedField::AlbumArtist,
+            CachedField::Year,
+            CachedField::Codec,
+        ] {
+            for sort in [
+                vec![SortKey::ascending(field)],
+                vec![SortKey::descending(field)],
+            ] {
+                let mut expected = tracks.clone();
+                expected.sort_by(|a, b| {
+                    let by_path = match sort[0].direction {
+                        SortDirection::Ascending => a.path.cmp(&b.path),
+                        SortDirection::Descending => b.path.cmp(&a.path),
+                    };
+                    a.sort_key(&sort, &collation)
+                        .cmp(&b.sort_key(&sort, &collation))
+                        .then(by_path)
+                });
+                let expected: Vec<_> = expected.iter().map(|t| &t.path).collect();
+                let sorted = database.tracks(&sort).unwrap();
+                let sorted: Vec<_> = sorted.iter().map(|t| &t.path).collect();
+                assert_eq!(sorted, expected, "{sort:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn pages_of_tracks_use_the_sort_indexes() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        let tracks: Vec<_> = ["e", "d", "c", "b", "a"]
+            .iter()
+            .map(|path| track(path, &["Abba"], 1976))
+            .collect();
+        database.sync_tracks(&tracks).unwrap();
+
+        let sort = [SortKey::ascending(CachedField::Title)];
+        assert_eq!(database.track_count(None).unwrap(), 5);
+        let page = database.tracks_page(&sort, None, 1, 2).unwrap();
+        let paths: Vec<_> = page.iter().map(|t| t.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["b", "c"]);
+        assert_eq!(
+            database
+                .track_position(Path::new("d"), &sort, None)
+                .unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            database
+                .track_position(Path::new("z"), &sort, None)
+                .unwrap(),
+            None
+        );
+
+        let plan: Vec<String> = database
+            .connection
+            .prepare(&format!(
+                "EXPLAIN QUERY PLAN SELECT path FROM tracks ORDER BY {} LIMIT 2",
+                LibraryDatabase::order_by(&sort)
+            ))
+            .unwrap()
+            .query_map([], |row| row.get(3))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert!(
+            plan.iter().any(|step| step.contains("tracks_title_key")),
+            "{plan:?}"
+        );
+    }
+
+    #[test]
+    fn pages_can_be_limited_to_a_root() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        let mut tracks: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|path| track(path, &["Abba"], 1976))
+            .collect();
+        for track in &mut tracks[1..3] {
+            track.root = Some("My NAS".to_owned());
+        }
+        database.sync_tracks(&tracks).unwrap();
+
+        let sort = [SortKey::ascending(CachedField::Title)];
+        let root = Some("My NAS");
+        assert_eq!(database.track_count(root).unwrap(), 2);
+        let page = database.tracks_page(&sort, root, 0, 10).unwrap();
+        let paths: Vec<_> = page.iter().map(|t| t.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["b", "c"]);
+        assert_eq!(
+            database
+                .track_position(Path::new("c"), &sort, root)
+                .unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            database
+                .track_position(Path::new("d"), &sort, root)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn sort_keys_are_updated_when_the_collation_changes() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        database
+            .sync_tracks(&[
+                track("a", &["The Beatles"], 1965),
+                track("b", &["Abba"], 1976),
+                track("c", &["Cream"], 1966),
+            ])
+            .unwrap();
+        let sort = [SortKey::ascending(CachedField::Artist)];
+        let paths = |database: &LibraryDatabase| -> Vec<PathBuf> {
+            database
+                .tracks(&sort)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.path)
+                .collect()
+        };
+        assert_eq!(paths(&database), ["b", "c", "a"].map(PathBuf::from));
+
+        database.collation.ignored_articles = vec!["The".to_owned()];
+        database.update_sort_keys().unwrap();
+        assert_eq!(paths(&database), ["b", "a", "c"].map(PathBuf::from));
+        assert_eq!(
+            database.tracks_by_artist("Beatles", &sort).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn single_tracks_are_written_and_removed() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        database
+            .sync_tracks(&[
+                track("a/1", &["Abba"], 1976),
+                track("a/2", &["Abba"], 1976),
+                track("ab/1", &["Abba"], 1977),
+            ])
+            .unwrap();
+        database.set_rating(Path::new("ab/1"), Some(6)).unwrap();
+
+        let mut edited = track("a/1", &["ABBA"], 1976);
+        edited.title = Some("Dancing Queen".to_owned());
+        database
+            .upsert_tracks(&[edited, track("b/1", &["Cream"], 1966)])
+            .unwrap();
+        database.remove_tracks(&[PathBuf::from("a/2")]).unwrap();
+
+        let under_a = database.tracks_under(&[PathBuf::from("a")]).unwrap();
+        assert_eq!(under_a.len(), 1);
+        assert_eq!(under_a[0].title.as_deref(), Some("Dancing Queen"));
+        assert_eq!(under_a[0].artists, ["ABBA"]);
+        assert_eq!(database.track_count(None).unwrap(), 3);
+        assert_eq!(database.rating(Path::new("ab/1")).unwrap(), Some(6));
+    }
+
+    #[test]
+    fn search_matches_every_word_and_filter() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        let mut tracks = [
+            track("a", &["The Beatles"], 1965),
+            track("b", &["Beatles", "Billy Preston"], 1969),
+            track("c", &["Abba"], 1976),
+        ];
+        tracks[1].album = Some("Let It Be".to_owned());
+        tracks[2].album = Some("100% Abba".to_owned());
+        tracks[2].root = Some("NAS".to_owned());
+        database.sync_tracks(&tracks).unwrap();
+
+        let search = |words: &[&str], filters: &[TrackFilter]| -> Vec<PathBuf> {
+            let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+            database
+                .search(&words, filters, &[SortKey::ascending(CachedField::Year)])
+                .unwrap()
+                .into_iter()
+                .map(|t| t.path)
+                .collect()
+        };
+        assert_eq!(search(&["beat"], &[]), ["a", "b"].map(PathBuf::from));
+        assert_eq!(search(&["beat", "PRESTON"], &[]), [PathBuf::from("b")]);
+        assert_eq!(search(&["0%"], &[]), [PathBuf::from("c")]);
+        assert!(search(&["1_0"], &[]).is_empty());
+        assert_eq!(
+            search(&[], &[TrackFilter::Root("nas".to_owned())]),
+            [PathBuf::from("c")]
+        );
+    }
+
+    #[test]
+    fn album_tracks_ignore_case() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        let mut tracks = [
+            track("a", &["Abba"], 1976),
+            track("b", &["abba"], 1976),
+            track("c", &["Cream"], 1976),
+            track("d", &["Abba"], 1976),
+        ];
+        tracks[0].album = Some("Arrival".to_owned());
+        tracks[1].album = Some("arrival".to_owned());
+        tracks[2].album = Some("Arrival".to_owned());
+        database.sync_tracks(&tracks).unwrap();
+
+        let paths = |track: &Track| -> Vec<PathBuf> {
+            database
+                .album_tracks(track, &SortKey::album_order())
+                .unwrap()
+                .into_iter()
+                .map(|t| t.path)
+                .collect()
+        };
+        assert_eq!(paths(&tracks[1]), ["a", "b"].map(PathBuf::from));
+        assert_eq!(paths(&tracks[3]), [PathBuf::from("d")]);
+    }
+
+    #[test]
+    fn playlists_keep_their_order() {
+        let mut database =
+            LibraryDatabase::open(Path::new(":memory:"), Collation::default()).unwrap();
+        let tracks = [
+            track("a", &["Abba"], 1976),
+            track("b", &["Abba"], 1976),
+            track("c", &["Abba"], 1976),
+        ];
+        database.sync_tracks(&tracks).unwrap();
+
+        let queue = [tracks[2].clone(), tracks[0].clone(), tracks[2].clone()];
+        database.save_playlist("Mix", &queue).unwrap();
+        database.save_playlist("Another", &tracks[..1]).unwrap();
+        assert_eq!(database.playlists().unwrap(), ["Another", "Mix"]);
+
+        let mix = database.playlist_tracks("Mix").unwrap();
+        let paths: Vec<_> = mix.iter().map(|t| t.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["c", "a", "c"]);
+        assert_eq!(mix[0].artists, ["Abba"]);
+
+        // Saving under the same name replaces it, and tracks leaving the library leave it
+        database.save_playlist("Mix", &tracks[1..]).unwrap();
+        database.sync_tracks(&tracks[..2]).unwrap();
+        let mix = database.playlist_tracks("Mix").unwrap();
+        assert_eq!(mix.len(), 1);
+        assert_eq!(mix[0].path, Path::new("b"));
+
+        database.delete_playlist("Mix").unwrap();
+        assert_eq!(database.playlists().unwrap(), ["Another"]);
+        assert!(database.playlist_tracks("Mix").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern, PatternError};
+
+/// File with patterns to exclude from the library, relative to the directory it's in
+pub(crate) const IGNORE_FILE: &str = ".minimignore";
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    // `*` shouldn't match across directories, like in a .gitignore
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Clone, Debug)]
+/// A line from a .gitignore-style file
+pub(crate) struct IgnorePattern {
+    pattern: Pattern,
+    /// Whether the pattern is matched against the whole relative path, rather than just the
+    /// file name, i.e., it contains a `/` before the end
+    anchored: bool,
+    /// Whether the pattern ends with a `/`, so it only matches directories
+    directory_only: bool,
+    /// Whether the pattern starts with a `!`, so it includes paths excluded by earlier patterns
+    negated: bool,
+}
+
+impl IgnorePattern {
+    /// Parses a line, returning `None` for blank lines and comments
+    pub fn parse(line: &str) -> Option<Result<Self, PatternError>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (directory_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+
+        Some(Pattern::new(line).map(|pattern| Self {
+            pattern,
+            anchored,
+            directory_only,
+            negated,
+        }))
+    }
+
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+
+        if self.anchored {
+            self.pattern.matches_path_with(relative, MATCH_OPTIONS)
+        } else {
+            relative.file_name().is_some_and(|name| {
+                self.pattern
+                    .matches_with(&name.to_string_lossy(), MATCH_OPTIONS)
+            })
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Patterns that apply to a directory, each relative to the directory it came from
+pub(crate) struct IgnoreRules {
+    rules: Vec<(PathBuf, IgnorePattern)>,
+}
+
+impl IgnoreRules {
+    pub fn add(&mut self, base: &Path, patterns: impl IntoIterator<Item = IgnorePattern>) {
+        self.rules.extend(
+            patterns
+                .into_iter()
+                .map(|pattern| (base.to_owned(), pattern)),
+        );
+    }
+
+    /// Adds the patterns from the [`IGNORE_FILE`] in `dir`, if there is one
+    ///
+    /// Invalid patterns are skipped, since there's nowhere to report them during a scan.
+    pub fn add_ignore_file(&mut self, dir: &Path) {
+        let Ok(contents) = std::fs::read_to_string(dir.join(IGNORE_FILE)) else {
+            return;
+        };
+        let patterns = contents
+            .lines()
+            .filter_map(IgnorePattern::parse)
+            .filter_map(Result::ok);
+        self.add(dir, patterns);
+    }
+
+    /// Whether `path` is excluded, going by the last pattern that matches it
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(base, pattern)| {
+                path.strip_prefix(base)
+                    .is_ok_and(|relative| pattern.matches(relative, is_dir))
+            })
+            .is_some_and(|(_, pattern)| !pattern.negated)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gitignore_style_patterns() {
+        let mut rules = IgnoreRules::default();
+        let patterns = [
+            "# comment",
+            "",
+            "*.log",
+            "/Podcasts/",
+            "live/**",
+            "!keep.log",
+        ];
+        rules.add(
+            Path::new("/music"),
+            patterns
+                .into_iter()
+                .filter_map(IgnorePattern::parse)
+                .map(Result::unwrap),
+        );
+        let excluded = |path: &str, is_dir| rules.is_excluded(Path::new(path), is_dir);
+
+        assert!(excluded("/music/Album/rip.log", false));
+        assert!(!excluded("/music/Album/keep.log", false));
+        assert!(excluded("/music/Podcasts", true));
+        assert!(!excluded("/music/Podcasts", false));
+        assert!(!excluded("/music/Artist/Podcasts", true));
+        assert!(excluded("/music/live/2001/01.flac", false));
+        assert!(!excluded("/music/Artist/live/01.flac", false));
+        assert!(!excluded("/elsewhere/rip.log", false));
+    }
+}
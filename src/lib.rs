@@ -1,9 +1,17 @@
 #![forbid(unsafe_code)]
 
+mod autotag;
 mod cache;
 mod config;
+mod crossfade;
+mod database;
+mod ignore;
+mod library;
 mod paths;
 mod player;
+mod shuffle;
+mod speed;
+mod template;
 mod theme;
 /// Types related to tracks
 pub mod track;
@@ -0,0 +1,800 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{Result, eyre::eyre};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{
+    Track,
+    cache::{self, Cache},
+    config::{Config, LibraryBackend, LibraryRoot},
+    database::LibraryDatabase,
+    ignore::{IgnorePattern, IgnoreRules},
+    paths,
+    template::PathTemplate,
+    track::{Collation, SortKey},
+};
+
+#[derive(Clone, Debug, Default)]
+/// Where to look for tracks, and which files to skip
+pub(crate) struct ScanOptions {
+    pub roots: Vec<LibraryRoot>,
+    /// Lowercase extensions of the files to read
+    pub extensions: HashSet<String>,
+    /// Patterns from the config file, relative to each root
+    pub exclude: Vec<IgnorePattern>,
+    pub follow_symlinks: bool,
+    /// Templates for filling in tags that are missing, in order of preference
+    pub templates: Vec<PathTemplate>,
+}
+
+impl ScanOptions {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            roots: unique_roots(&config.library_roots),
+            extensions: config
+                .audio_extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_lowercase())
+                .collect(),
+            exclude: config
+                .exclude
+                .iter()
+                .filter_map(|line| IgnorePattern::parse(line))
+                .collect::<Result<_, _>>()?,
+            follow_symlinks: config.follow_symlinks,
+            templates: config
+                .path_templates
+                .iter()
+                .map(|template| PathTemplate::parse(template))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Labels a track with its root and fills in missing tags from the path templates
+    fn prepare(&self, root: &LibraryRoot, label: &str, mut track: Track) -> Track {
+        track.root = Some(label.to_owned());
+        track.infer_from_path(&root.path, &self.templates);
+        track
+    }
+
+    /// The innermost root that `path` is in, so tracks in nested roots belong to only one root
+    pub fn root_of(&self, path: &Path) -> Option<&LibraryRoot> {
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.components().count())
+    }
+
+    /// Whether `root` can be scanned, given whether the library has tracks in it
+    ///
+    /// An unmounted drive often leaves an empty mount point behind, so a root without any audio
+    /// files is taken to be unavailable while the library has tracks in it, rather than every
+    /// one of them being removed.
+    fn is_available(&self, root: &LibraryRoot, has_tracks: bool) -> bool {
+        root.path.is_dir()
+            && (!has_tracks || self.audio_files(&root.path, &root.path).next().is_some())
+    }
+
+    /// Whether `root` is the root that `path` belongs to
+    fn is_in_root(&self, root: &Path, path: &Path) -> bool {
+        self.root_of(path).is_some_and(|owner| owner.path == root)
+    }
+
+    fn is_audio_file(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|extension| {
+            self.extensions
+                .contains(&extension.to_string_lossy().to_lowercase())
+        })
+    }
+
+    /// Audio files under `path`, which is in `root`, leaving out excluded files and directories
+    ///
+    /// Symbolic links are followed if enabled, and links back into a directory being scanned
+    /// are skipped rather than followed forever. Other roots nested inside `root` are left to
+    /// be scanned on their own.
+    fn audio_files<'a>(
+        &'a self,
+        root: &'a Path,
+        path: &'a Path,
+    ) -> impl Iterator<Item = DirEntry> + 'a {
+        let mut rules = DirectoryRules::new(root, &self.exclude);
+
+        WalkDir::new(path)
+            .follow_links(self.follow_symlinks)
+            .into_iter()
+            .filter_entry(move |entry| {
+                if entry.depth() > 0 {
+                    let is_dir = entry.file_type().is_dir();
+                    if is_dir && self.roots.iter().any(|other| other.path == entry.path()) {
+                        return false;
+                    }
+                    return !rules.is_excluded(entry.path(), is_dir);
+                }
+                // When rescanning part of a root, the directories above it may be excluded too
+                !entry
+                    .path()
+                    .ancestors()
+                    .take_while(|ancestor| *ancestor != root && ancestor.starts_with(root))
+                    .any(|ancestor| {
+                        let is_dir = ancestor != entry.path() || entry.file_type().is_dir();
+                        rules.is_excluded(ancestor, is_dir)
+                    })
+            })
+            // Errors include symbolic link loops
+            .filter_map(|e| e.ok())
+            .filter(|f| f.file_type().is_file() && self.is_audio_file(f.path()))
+    }
+}
+
+/// The roots without those listed more than once, e.g., with and without a symbolic link
+fn unique_roots(roots: &[LibraryRoot]) -> Vec<LibraryRoot> {
+    let mut seen = HashSet::new();
+    roots
+        .iter()
+        .filter(|root| seen.insert(root.path.canonicalize().unwrap_or(root.path.clone())))
+        .cloned()
+        .collect()
+}
+
+/// The exclude rules for each directory in a root, including those from ignore files above it
+struct DirectoryRules<'a> {
+    root: &'a Path,
+    exclude: &'a [IgnorePattern],
+    directories: HashMap<PathBuf, IgnoreRules>,
+}
+
+impl<'a> DirectoryRules<'a> {
+    fn new(root: &'a Path, exclude: &'a [IgnorePattern]) -> Self {
+        Self {
+            root,
+            exclude,
+            directories: HashMap::new(),
+        }
+    }
+
+    fn is_excluded(&mut self, path: &Path, is_dir: bool) -> bool {
+        path.parent()
+            .is_some_and(|parent| self.rules(parent).is_excluded(path, is_dir))
+    }
+
+    fn rules(&mut self, dir: &Path) -> &IgnoreRules {
+        if !self.directories.contains_key(dir) {
+            let mut rules = match dir.parent() {
+                Some(parent) if dir != self.root && dir.starts_with(self.root) => {
+                    self.rules(parent).clone()
+                }
+                _ => {
+                    let mut rules = IgnoreRules::default();
+                    rules.add(dir, self.exclude.iter().cloned());
+                    rules
+                }
+            };
+            rules.add_ignore_file(dir);
+            self.directories.insert(dir.to_owned(), rules);
+        }
+
+        &self.directories[dir]
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// What changed in the library during a scan
+pub(crate) struct ScanSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// Library roots that don't exist, couldn't be reached or were empty, whose cached tracks
+    /// were kept
+    pub unavailable: Vec<PathBuf>,
+}
+
+impl ScanSummary {
+    fn merge(&mut self, other: ScanSummary) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.removed += other.removed;
+        self.unavailable.extend(other.unavailable);
+    }
+}
+
+impl fmt::Display for ScanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} new, {} changed, {} removed",
+            self.added, self.updated, self.removed
+        )?;
+        if !self.unavailable.is_empty() {
+            let paths: Vec<_> = self
+                .unavailable
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            write!(f, " (not found: {})", paths.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Something that happened while scanning, for reporting progress
+pub(crate) enum ScanEvent {
+    /// A file was found
+    Found,
+    /// A file was unchanged since it was cached
+    Unchanged,
+    Added(Track),
+    Updated(Track),
+    /// An audio file couldn't be read
+    Failed,
+    /// The scan is done, with every track that's now in the library
+    Finished(Vec<Track>, ScanSummary),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Counts of files seen so far during a scan
+pub(crate) struct ScanProgress {
+    pub found: usize,
+    pub unchanged: usize,
+    pub parsed: usize,
+    pub failed: usize,
+}
+
+/// The result of scanning a single file
+enum Scanned {
+    Unchanged(Track),
+    Added(Track),
+    Updated(Track),
+    /// Not a track, or couldn't be read. Holds whether the file was in the cache.
+    Skipped(bool),
+}
+
+/// Scans every library root, reusing cached tracks whose files haven't changed
+///
+/// Roots that aren't available, e.g., an unmounted drive, keep their cached tracks and are
+/// listed in the summary. That includes roots with cached tracks where no files are found. Cached tracks outside of every root are dropped. Tracks in a root
+/// nested inside another belong only to the inner root.
+pub(crate) fn scan_roots(
+    options: &ScanOptions,
+    mut cached: Vec<Track>,
+    on_event: impl Fn(ScanEvent) + Sync,
+) -> (Vec<Track>, ScanSummary) {
+    let mut tracks = Vec::new();
+    let mut summary = ScanSummary::default();
+    for root in &options.roots {
+        let (affected, rest) = cached
+            .into_iter()
+            .partition(|track| options.is_in_root(&root.path, &track.path));
+        cached = rest;
+
+        if options.is_available(root, !affected.is_empty()) {
+            let (scanned, changes) = scan(options, root, &root.path, affected, &on_event);
+            tracks.extend(scanned);
+            summary.merge(changes);
+        } else {
+            tracks.extend(affected);
+            summary.unavailable.push(root.path.clone());
+        }
+    }
+    summary.removed += cached.len();
+
+    (tracks, summary)
+}
+
+/// Combines the tracks from a finished scan with the library as it is now
+///
+/// Tracks under the `changed` paths were updated, added or removed after the scan started, e.g.,
+/// by editing tags, so the library's copies are kept for them. Everything else comes from the
+/// scan.
+pub(crate) fn merge_scan(
+    current: Vec<Track>,
+    scanned: Vec<Track>,
+    changed: &[PathBuf],
+) -> Vec<Track> {
+    let is_changed = |track: &Track| changed.iter().any(|path| track.path.starts_with(path));
+
+    scanned
+        .into_iter()
+        .filter(|track| !is_changed(track))
+        .chain(current.into_iter().filter(is_changed))
+        .collect()
+}
+
+/// Scans `path` in `root` for tracks, reusing cached tracks whose files haven't changed
+///
+/// Only files that are new, or whose modification time or size differ from the cache, are
+/// read, in parallel. Cached tracks whose files have disappeared or are now excluded are
+/// dropped. `on_event` is called from the worker threads as files are found and read.
+fn scan(
+    options: &ScanOptions,
+    root: &LibraryRoot,
+    path: &Path,
+    cached: Vec<Track>,
+    on_event: impl Fn(ScanEvent) + Sync,
+) -> (Vec<Track>, ScanSummary) {
+    let mut cached: HashMap<PathBuf, Track> = cached
+        .into_iter()
+        .map(|track| (track.path.clone(), track))
+        .collect();
+
+    let label = root.name();
+    let scanned: Vec<Scanned> = options
+        .audio_files(&root.path, path)
+        .map(|file| {
+            on_event(ScanEvent::Found);
+            let previous = cached.remove(file.path());
+            (file, previous)
+        })
+        .par_bridge()
+        .map(|(file, previous)| scan_file(options, root, &label, file.path(), previous, &on_event))
+        .collect();
+
+    let mut tracks = Vec::new();
+    let mut summary = ScanSummary {
+        removed: cached.len(),
+        ..Default::default()
+    };
+    for scanned in scanned {
+        match scanned {
+            Scanned::Unchanged(track) => tracks.push(track),
+            Scanned::Added(track) => {
+                summary.added += 1;
+                tracks.push(track);
+            }
+            Scanned::Updated(track) => {
+                summary.updated += 1;
+                tracks.push(track);
+            }
+            // The file changed into something we can't read anymore
+            Scanned::Skipped(true) => summary.removed += 1,
+            Scanned::Skipped(false) => {}
+        }
+    }
+
+    (tracks, summary)
+}
+
+fn scan_file(
+    options: &ScanOptions,
+    root: &LibraryRoot,
+    label: &str,
+    path: &Path,
+    previous: Option<Track>,
+    on_event: &impl Fn(ScanEvent),
+) -> Scanned {
+    let was_cached = previous.is_some();
+    let Ok(metadata) = path.metadata() else {
+        return Scanned::Skipped(was_cached);
+    };
+
+    // The root's label or the path templates may have changed since the track was cached
+    let prepare = |track| options.prepare(root, label, track);
+
+    match previous {
+        Some(track) if track.matches_file(&metadata) => {
+            on_event(ScanEvent::Unchanged);
+            Scanned::Unchanged(prepare(track))
+        }
+        _ => match Track::try_from(path).map(prepare) {
+            Ok(track) if was_cached => {
+                on_event(ScanEvent::Updated(track.clone()));
+                Scanned::Updated(track)
+            }
+            Ok(track) => {
+                on_event(ScanEvent::Added(track.clone()));
+                Scanned::Added(track)
+            }
+            Err(_) => {
+                on_event(ScanEvent::Failed);
+                Scanned::Skipped(was_cached)
+            }
+        },
+    }
+}
+
+/// Opens the library database, if the config uses one rather than the CSV cache
+pub(crate) fn open_database(config: &Config) -> Result<Option<LibraryDatabase>> {
+    if config.library_backend != LibraryBackend::Sqlite {
+        return Ok(None);
+    }
+
+    let path = paths::library_database_file().ok_or(eyre!("Missing cache dir?"))?;
+    let collation = Collation {
+        ignored_articles: config.ignored_articles.clone(),
+    };
+    Ok(Some(LibraryDatabase::open(&path, collation)?))
+}
+
+/// Reads the library from the database if there is one, otherwise from the CSV cache
+pub(crate) fn load_library(database: Option<&LibraryDatabase>, sort: &[SortKey]) -> Result<Cache> {
+    if let Some(database) = database
+        && !database.is_empty()?
+    {
+        return Ok(Cache {
+            tracks: database.tracks(sort)?,
+            corrupted: 0,
+        });
+    }
+
+    // A new database starts out with the tracks from the CSV cache, if there is one
+    let path = paths::library_cache_file().ok_or(eyre!("Missing cache dir?"))?;
+    if !path.exists() {
+        return Ok(Cache {
+            tracks: Vec::new(),
+            corrupted: 0,
+        });
+    }
+    cache::read_cache(&path)
+}
+
+/// Writes the library to the database if there is one, otherwise to the CSV cache
+pub(crate) fn save_library(database: Option<&mut LibraryDatabase>, tracks: &[Track]) -> Result<()> {
+    match database {
+        Some(database) => database.sync_tracks(tracks),
+        None => {
+            let path = paths::library_cache_file().ok_or(eyre!("Missing cache dir?"))?;
+            cache::write_cache(&path, tracks)
+        }
+    }
+}
+
+/// A scan running on a background thread
+pub(crate) struct BackgroundScan {
+    events: Receiver<ScanEvent>,
+}
+
+impl BackgroundScan {
+    pub fn start(options: ScanOptions, cached: Vec<Track>) -> Self {
+        let (sender, events) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (tracks, summary) = scan_roots(&options, cached, |event| {
+                let _ = sender.send(event);
+            });
+            let _ = sender.send(ScanEvent::Finished(tracks, summary));
+        });
+
+        Self { events }
+    }
+
+    /// Events from the scan since the last call
+    pub fn events(&self) -> impl Iterator<Item = ScanEvent> + '_ {
+        self.events.try_iter()
+    }
+}
+
+/// Rescans the given files or directories, updating the matching tracks in `tracks`
+///
+/// Returns the tracks that are now under those paths, along with what changed. Paths outside of
+/// the library roots, or in a root that has become unavailable, are left alone.
+pub(crate) fn rescan_paths(
+    tracks: &mut Vec<Track>,
+    options: &ScanOptions,
+    paths: &[PathBuf],
+) -> (Vec<Track>, ScanSummary) {
+    let mut paths = paths.to_vec();
+    paths.sort();
+    // Paths inside a directory that's being rescanned anyway are redundant
+    paths.dedup_by(|path, parent| path.starts_with(parent));
+
+    let mut rescanned = Vec::new();
+    let mut summary = ScanSummary::default();
+    for path in paths {
+        let Some(root) = options.root_of(&path) else {
+            continue;
+        };
+
+        let (affected, mut rest): (Vec<Track>, Vec<Track>) =
+            std::mem::take(tracks).into_iter().partition(|track| {
+                track.path.starts_with(&path) && options.is_in_root(&root.path, &track.path)
+            });
+        if !options.is_available(root, !affected.is_empty()) {
+            rest.extend(affected);
+            *tracks = rest;
+            if !summary.unavailable.contains(&root.path) {
+                summary.unavailable.push(root.path.clone());
+            }
+            continue;
+        }
+        *tracks = rest;
+
+        // Paths that no longer exist yield no files, so their tracks are dropped
+        let (scanned, changes) = scan(options, root, &path, affected, |_| {});
+        summary.merge(changes);
+        tracks.extend(scanned.iter().cloned());
+        rescanned.extend(scanned);
+    }
+
+    (rescanned, summary)
+}
+
+/// Watches the library for files being added, removed, renamed or modified
+pub(crate) struct LibraryWatcher {
+    // Events stop once the watcher is dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<PathBuf>,
+    pending: HashSet<PathBuf>,
+    last_event: Instant,
+}
+
+impl LibraryWatcher {
+    /// How long to wait for changes to settle, since copying or tagging a file emits many events
+    const DEBOUNCE: Duration = Duration::from_millis(500);
+
+    /// Watches every root that's currently available
+    pub fn new(roots: &[LibraryRoot]) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                // Ignore reads, including our own when playing files or loading track art
+                if let Ok(event) = event
+                    && !matches!(event.kind, EventKind::Access(_))
+                {
+                    for path in event.paths {
+                        let _ = sender.send(path);
+                    }
+                }
+            })?;
+        for root in roots.iter().filter(|root| root.path.is_dir()) {
+            watcher.watch(&root.path, RecursiveMode::Recursive)?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            pending: HashSet::new(),
+            last_event: Instant::now(),
+        })
+    }
+
+    /// Paths that have changed, once no new changes have come in for a while
+    pub fn changed_paths(&mut self) -> Option<Vec<PathBuf>> {
+        for path in self.events.try_iter() {
+            self.pending.insert(path);
+            self.last_event = Instant::now();
+        }
+
+        if self.pending.is_empty() || self.last_event.elapsed() < Self::DEBOUNCE {
+            return None;
+        }
+
+        Some(self.pending.drain().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn options(root: &Path) -> ScanOptions {
+        ScanOptions {
+            roots: vec![LibraryRoot::new(root.to_owned())],
+            extensions: ["flac".to_owned(), "mp3".to_owned()].into(),
+            ..Default::default()
+        }
+    }
+
+    /// A cached track for a new, empty file, which matches it so it isn't read
+    fn unchanged_track(path: PathBuf) -> Track {
+        std::fs::write(&path, "").unwrap();
+        let modified = path.metadata().unwrap().modified().unwrap();
+        Track {
+            path,
+            file_modified: modified
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+                .try_into()
+                .unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scan_drops_missing_files() {
+        let root = tempfile::tempdir().unwrap();
+        // A root without any files left would be taken for an unmounted drive
+        let kept = unchanged_track(root.path().join("kept.flac"));
+        let track = Track {
+            path: root.path().join("deleted.flac"),
+            ..Default::default()
+        };
+        let (tracks, summary) =
+            scan_roots(&options(root.path()), vec![track, kept.clone()], |_| {});
+
+        assert_eq!(tracks, [kept]);
+        assert_eq!(
+            summary,
+            ScanSummary {
+                removed: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn merge_scan_keeps_changes_made_during_the_scan() {
+        let track = |path: &str, title: &str| Track {
+            path: PathBuf::from(path),
+            title: Some(title.to_owned()),
+            ..Default::default()
+        };
+        let current = vec![
+            track("/music/edited.flac", "New title"),
+            track("/music/stale.flac", "Cached"),
+            track("/music/new/added.flac", "Added"),
+        ];
+        let scanned = vec![
+            track("/music/edited.flac", "Old title"),
+            track("/music/stale.flac", "Scanned"),
+            track("/music/new/deleted.flac", "Deleted"),
+        ];
+        let changed = [
+            PathBuf::from("/music/edited.flac"),
+            PathBuf::from("/music/new"),
+        ];
+
+        let mut merged = merge_scan(current, scanned, &changed);
+        merged.sort_by(|a, b| a.path.cmp(&b.path));
+        let titles: Vec<_> = merged.iter().map(|t| t.title.as_deref().unwrap()).collect();
+        assert_eq!(titles, ["New title", "Added", "Scanned"]);
+    }
+
+    #[test]
+    fn rescan_paths_only_touches_changed_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let track = |path: &str| Track {
+            path: root.join(path),
+            ..Default::default()
+        };
+        let mut tracks = vec![track("a/1.flac"), track("a/2.flac"), track("b/1.flac")];
+        std::fs::create_dir(root.join("b")).unwrap();
+        std::fs::write(root.join("b/1.flac"), "").unwrap();
+
+        let (rescanned, summary) = rescan_paths(
+            &mut tracks,
+            &options(root),
+            &[root.join("a/2.flac"), root.join("a")],
+        );
+
+        assert!(rescanned.is_empty());
+        assert_eq!(summary.removed, 2);
+        assert_eq!(tracks, [track("b/1.flac")]);
+    }
+
+    #[test]
+    fn unavailable_roots_keep_their_tracks() {
+        let temp = tempfile::tempdir().unwrap();
+        let available = temp.path().join("music");
+        std::fs::create_dir(&available).unwrap();
+        let missing = LibraryRoot {
+            path: temp.path().join("unmounted"),
+            label: Some("NAS".to_owned()),
+        };
+        let mut options = options(&available);
+        options.roots.push(missing.clone());
+
+        let track = |path: PathBuf| Track {
+            path,
+            ..Default::default()
+        };
+        let unchanged = unchanged_track(available.join("unchanged.flac"));
+        let kept = track(missing.path.join("a.flac"));
+        let cached = vec![
+            track(available.join("deleted.flac")),
+            unchanged.clone(),
+            kept.clone(),
+            track("/elsewhere/b.flac".into()),
+        ];
+        let (tracks, summary) = scan_roots(&options, cached, |_| {});
+
+        assert_eq!(tracks, [unchanged, kept]);
+        assert_eq!(summary.removed, 2);
+        assert_eq!(summary.unavailable, [missing.path]);
+    }
+
+    #[test]
+    fn empty_roots_with_cached_tracks_are_unavailable() {
+        let temp = tempfile::tempdir().unwrap();
+        let mount_point = temp.path().join("nas");
+        let empty = temp.path().join("empty");
+        for dir in [&mount_point, &empty] {
+            std::fs::create_dir(dir).unwrap();
+        }
+        let mut options = options(&mount_point);
+        options.roots.push(LibraryRoot::new(empty.clone()));
+
+        let cached = Track {
+            path: mount_point.join("album/a.flac"),
+            ..Default::default()
+        };
+        let (tracks, summary) = scan_roots(&options, vec![cached.clone()], |_| {});
+        assert_eq!(tracks, std::slice::from_ref(&cached));
+        assert_eq!(summary.removed, 0);
+        assert_eq!(summary.unavailable, std::slice::from_ref(&mount_point));
+
+        let mut tracks = vec![cached.clone()];
+        let (rescanned, summary) =
+            rescan_paths(&mut tracks, &options, &[mount_point.join("album")]);
+        assert!(rescanned.is_empty());
+        assert_eq!(tracks, [cached]);
+        assert_eq!(summary.unavailable, [mount_point]);
+    }
+
+    #[test]
+    fn audio_files_skip_excluded_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        for dir in ["skip", "sub"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "a.FLAC",
+            "cover.jpg",
+            "skip/b.flac",
+            "sub/c.mp3",
+            "sub/d.flac",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        std::fs::write(root.join("sub").join(crate::ignore::IGNORE_FILE), "*.mp3\n").unwrap();
+
+        let mut options = options(root);
+        options.exclude = vec![IgnorePattern::parse("/skip/").unwrap().unwrap()];
+        let files = |path: &Path| {
+            let mut files: Vec<_> = options
+                .audio_files(root, path)
+                .map(|file| file.into_path())
+                .collect();
+            files.sort();
+            files
+        };
+        let all = files(root);
+        let rescanned = files(&root.join("skip/b.flac"));
+
+        assert_eq!(all, [root.join("a.FLAC"), root.join("sub/d.flac")]);
+        assert!(rescanned.is_empty());
+    }
+
+    #[test]
+    fn nested_roots_are_scanned_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let outer = dir.path().join("music");
+        let inner = outer.join("nas");
+        std::fs::create_dir_all(&inner).unwrap();
+        for file in [outer.join("a.flac"), inner.join("b.flac")] {
+            std::fs::write(file, "").unwrap();
+        }
+
+        let roots = unique_roots(&[
+            LibraryRoot::new(outer.clone()),
+            LibraryRoot::new(inner.clone()),
+            LibraryRoot::new(inner.join("..").join("nas")),
+        ]);
+        assert_eq!(roots.len(), 2);
+        let mut options = options(&outer);
+        options.roots = roots;
+
+        let files = |root: &Path| -> Vec<_> {
+            options
+                .audio_files(root, root)
+                .map(|file| file.into_path())
+                .collect()
+        };
+        assert_eq!(files(&outer), [outer.join("a.flac")]);
+        assert_eq!(files(&inner), [inner.join("b.flac")]);
+
+        let b = inner.join("b.flac");
+        assert_eq!(options.root_of(&b).unwrap().path, inner);
+        assert!(options.is_in_root(&inner, &b));
+        assert!(!options.is_in_root(&outer, &b));
+    }
+}
@@ -10,6 +10,10 @@ async fn main() -> Result<()> {
     let args = Args::parse();
 
     color_eyre::install()?;
+    if Player::run_command(&args)? {
+        return Ok(());
+    }
+
     let mut player = Player::new(args).await?;
     let mut terminal = ratatui::init();
     let result = player.run(&mut terminal).await;
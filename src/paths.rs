@@ -1,4 +1,7 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use color_eyre::eyre::{Result, eyre};
 
@@ -35,6 +38,20 @@ pub fn cache_dir() -> Option<PathBuf> {
     Some(path)
 }
 
+pub fn library_cache_file() -> Option<PathBuf> {
+    let mut path = self::cache_dir()?;
+    path.push("library.csv");
+
+    Some(path)
+}
+
+pub fn library_database_file() -> Option<PathBuf> {
+    let mut path = self::cache_dir()?;
+    path.push("library.db");
+
+    Some(path)
+}
+
 pub fn config_dir() -> Option<PathBuf> {
     let mut path = dirs::config_dir()?;
     path.push("minim");
@@ -48,9 +65,100 @@ pub fn config_file() -> Option<PathBuf> {
 
     Some(path)
 }
+
+pub fn playback_speeds_file() -> Option<PathBuf> {
+    let mut path = self::config_dir()?;
+    path.push("speeds.toml");
+
+    Some(path)
+}
+
 pub fn theme_dir() -> Option<PathBuf> {
     let mut path = self::config_dir()?;
     path.push("themes");
 
     Some(path)
 }
+
+/// Expands a leading `~` and `$NAME` or `${NAME}` environment variables in a path from the config
+///
+/// `HOME` and `XDG_MUSIC_DIR` fall back to the platform's home and music directories when they
+/// aren't set, since `XDG_MUSIC_DIR` usually only exists in `user-dirs.dirs`.
+pub(crate) fn expand_path(path: &Path) -> Result<PathBuf> {
+    let Some(path) = path.to_str() else {
+        return Ok(path.to_owned());
+    };
+
+    let (mut expanded, mut rest) = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            (home_dir()?.to_string_lossy().into_owned(), rest)
+        }
+        _ => (String::new(), path),
+    };
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, remaining) = match after.strip_prefix('{') {
+            Some(braced) => braced
+                .split_once('}')
+                .ok_or_else(|| eyre!("Missing closing brace in {path}"))?,
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                after.split_at(end)
+            }
+        };
+
+        if name.is_empty() {
+            // Not a variable, e.g., a `$` at the end of a directory name
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        expanded.push_str(&environment_variable(name)?);
+        rest = remaining;
+    }
+    expanded.push_str(rest);
+
+    Ok(PathBuf::from(expanded))
+}
+
+fn environment_variable(name: &str) -> Result<String> {
+    if let Ok(value) = std::env::var(name) {
+        return Ok(value);
+    }
+
+    let fallback = match name {
+        "HOME" => dirs::home_dir(),
+        "XDG_MUSIC_DIR" => dirs::audio_dir(),
+        _ => None,
+    };
+    fallback
+        .map(|path| path.to_string_lossy().into_owned())
+        .ok_or_else(|| eyre!("Environment variable {name} isn't set"))
+}
+
+fn home_dir() -> Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| eyre!("Couldn't find the home directory to expand ~"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expands_home_and_variables() {
+        let home = dirs::home_dir().unwrap();
+        let expand = |path: &str| expand_path(Path::new(path)).unwrap();
+
+        assert_eq!(expand("~"), home);
+        assert_eq!(expand("~/music"), home.join("music"));
+        assert_eq!(expand("$HOME/music"), home.join("music"));
+        assert_eq!(expand("${HOME}/music"), home.join("music"));
+        assert_eq!(expand("/music/~a$/b"), PathBuf::from("/music/~a$/b"));
+        assert!(expand_path(Path::new("$MINIM_UNSET_TEST_VARIABLE/music")).is_err());
+        assert!(expand_path(Path::new("${HOME/music")).is_err());
+    }
+}
@@ -1,14 +1,20 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs,
     io::Cursor,
+    ops::Range,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use clap::Parser;
-use color_eyre::{Result, eyre::eyre};
+use color_eyre::{Result, eyre::WrapErr};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MediaKeyCode};
+use glob::Pattern;
 use image::DynamicImage;
 use nucleo::{
     Injector, Nucleo,
@@ -20,20 +26,29 @@ use ratatui::{
     style::{Style, Stylize},
     text::{Line, Span, Text},
     widgets::{
-        Block, BorderType, Clear, LineGauge, Paragraph, Row, Scrollbar, ScrollbarOrientation,
-        ScrollbarState, Table, TableState,
+        Block, BorderType, Clear, LineGauge, List, ListState, Paragraph, Row, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Table, TableState,
     },
 };
 use ratatui_image::{StatefulImage, picker::Picker, protocol::StatefulProtocol};
 use rodio::{OutputStream, OutputStreamBuilder, Sink, Source};
 use tui_textarea::TextArea;
-use walkdir::WalkDir;
 
 use crate::{
-    config::Config,
+    autotag::{self, TagChange},
+    cache::Cache,
+    config::{Config, LibraryRoot},
+    crossfade::{Crossfade, Handover},
+    database::LibraryDatabase,
+    library::{
+        self, BackgroundScan, LibraryWatcher, ScanEvent, ScanOptions, ScanProgress, ScanSummary,
+    },
     paths,
+    shuffle::{self, ShuffleMode},
+    speed::{self, SavedSpeeds, SpeedControl, VariableSpeed},
+    template::PathTemplate,
     theme::Theme,
-    track::{CachedField, Track},
+    track::{CachedField, Collation, SortDirection, SortKey, Track, TrackFilter},
 };
 
 const PLACEHOLDER_IMAGE_BYTES: &[u8] = include_bytes!("../placeholder.png");
@@ -43,35 +58,115 @@ const PLACEHOLDER_IMAGE_BYTES: &[u8] = include_bytes!("../placeholder.png");
 /// Command-line arguments for the player
 pub struct Args {
     /// Where the player should look for files
+    #[arg(value_parser = existing_path)]
     dir: Option<PathBuf>,
 
     /// Reset library cache
     #[arg(short = 'c', long = "clean")]
     reset_cache: bool,
+
+    /// Load the library from a CSV file instead of the library store
+    #[arg(long, value_name = "FILE", value_parser = existing_path)]
+    import_csv: Option<PathBuf>,
+
+    /// Write the library to a CSV file once it has been scanned
+    #[arg(long, value_name = "FILE", value_parser = expanded_path)]
+    export_csv: Option<PathBuf>,
+
+    /// Write tags parsed from the paths of the tracks in the library, or in the given directory,
+    /// then exit, e.g., "{artist}/{album}/{track} - {title}"
+    #[arg(long, value_name = "TEMPLATE")]
+    tag_from_path: Option<String>,
+
+    /// Only show the changes --tag-from-path would make
+    #[arg(long, requires = "tag_from_path")]
+    dry_run: bool,
+}
+
+/// A path from the command line with `~` and environment variables expanded, as in the config
+/// file, for when the shell leaves them alone, e.g., in `--export-csv=~/library.csv`
+fn expanded_path(path: &str) -> std::result::Result<PathBuf, String> {
+    paths::expand_path(Path::new(path)).map_err(|e| e.to_string())
+}
+
+/// Like [`expanded_path`], but the path has to exist
+fn existing_path(path: &str) -> std::result::Result<PathBuf, String> {
+    let expanded = expanded_path(path)?;
+    if !expanded.exists() {
+        return Err(format!("{} doesn't exist", expanded.display()));
+    }
+    Ok(expanded)
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     Quit,
     ToggleHelp,
+    ToggleSortPicker,
+    SelectSortPickerRow(usize),
+    SortLibrary(Vec<SortKey>),
+    ToggleMark(Track),
+    ClearMarks,
+    OpenTagEditor(Vec<Track>),
+    CloseTagEditor,
+    OpenAutoTagger(Vec<Track>),
+    CloseAutoTagger,
+    ScrollAutoTagger(u16),
+    SaveAutoTags,
+    ShowTrackDetails(Track),
+    CloseTrackDetails,
+    ScrollTrackDetails(u16),
+    SelectTagEditorField(usize),
+    SaveTagEdits,
     FocusMainPanel,
     FocusSidebar,
     FocusLibrary,
     FocusSearchBar,
+    LibraryChanged(Vec<PathBuf>),
+    Scan(Vec<ScanEvent>),
+    TrackStarted(PathBuf),
+    ClearStatus,
     ShowSearchResults,
 
     PlayPause,
+    ToggleCrossfade,
+    SpeedUp,
+    SpeedDown,
+    ResetSpeed,
+    SaveSpeed(SpeedScope),
     NextTrack,
     PrevTrack,
+    SeekForward,
+    SeekBackward,
+    RestartTrack,
+    OpenSeekPrompt,
+    CloseSeekPrompt,
+    SubmitSeekPrompt,
+    /// Raises or lowers a track's rating by the given number of half stars
+    ChangeRating(Track, i8),
+    OpenPlaylists,
+    ClosePlaylists,
+    SelectPlaylistRow(usize),
+    QueuePlaylist(String),
+    /// Saves the queue as a playlist with the given name
+    SavePlaylist(String),
+    DeletePlaylist(String),
     QueueTrack(Track),
     QueueTrackNext(Track),
+    QueueAlbum(Track),
     RemoveFromQueue(usize),
     VolumeUp(usize),
     VolumeDown(usize),
     CycleRepeatMode,
+    CycleShuffleMode,
     ToggleTrackArt,
     SelectLibraryRow(usize),
+    CycleRootFilter,
     SelectSearchResultRow(usize),
+    ShowArtists,
+    SelectArtistRow(usize),
+    ShowArtistTracks(String),
+    SelectArtistTrackRow(usize),
     SelectSidebarQueueRow(usize),
 }
 
@@ -79,6 +174,7 @@ enum Message {
 struct PlayerState {
     quit: bool,
     show_help: bool,
+    show_sort_picker: bool,
     focus: PanelFocus,
     main_panel_view: MainPanelView,
 }
@@ -94,6 +190,7 @@ impl Default for PlayerState {
         Self {
             quit: false,
             show_help: false,
+            show_sort_picker: false,
             focus: PanelFocus::MainPanel,
             main_panel_view: MainPanelView::Library,
         }
@@ -105,6 +202,16 @@ enum MainPanelView {
     Library,
     SearchInput,
     SearchResults,
+    Artists,
+    ArtistTracks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What a saved playback speed applies to
+enum SpeedScope {
+    Track,
+    /// Every track in the current track's directory, including subdirectories
+    Directory,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -118,14 +225,25 @@ enum RepeatMode {
 #[derive(Debug, Clone)]
 struct PlayerSettings {
     repeat_mode: Arc<Mutex<RepeatMode>>,
+    shuffle_mode: ShuffleMode,
     show_track_art: bool,
+    /// Whether tracks fade into each other, which can be toggled while they play
+    crossfade: Arc<AtomicBool>,
+    crossfade_duration: Duration,
+    speed: SpeedControl,
+    saved_speeds: Arc<Mutex<SavedSpeeds>>,
 }
 
 impl Default for PlayerSettings {
     fn default() -> Self {
         Self {
             repeat_mode: Default::default(),
+            shuffle_mode: ShuffleMode::default(),
             show_track_art: true,
+            crossfade: Default::default(),
+            crossfade_duration: Duration::from_secs(Config::default_crossfade_duration()),
+            speed: SpeedControl::new(true, Weak::new()),
+            saved_speeds: Default::default(),
         }
     }
 }
@@ -139,6 +257,13 @@ struct PlaybackState {
     queue_index: Arc<Mutex<usize>>,
     /// Where to insert [`Track`]s when adding to middle of queue
     insertion_offset: Arc<Mutex<usize>>,
+    /// The track after the current one, if it's already been added to the sink
+    preload: Arc<Mutex<Option<Preload>>>,
+    /// Milliseconds into the current track, which the sink's position doesn't match once the
+    /// speed changes
+    position: Arc<AtomicU64>,
+    /// Why tracks couldn't be played, for the player to show
+    errors: Arc<Mutex<Vec<String>>>,
 }
 
 impl PlaybackState {
@@ -148,21 +273,99 @@ impl PlaybackState {
             queue: Arc::new(Mutex::new(Vec::new())),
             queue_index: Arc::new(Mutex::new(0)),
             insertion_offset: Arc::new(Mutex::new(0)),
+            preload: Arc::new(Mutex::new(None)),
+            position: Arc::new(AtomicU64::new(0)),
+            errors: Arc::new(Mutex::new(Vec::new())),
             sink: Arc::new(sink),
         }
     }
+
+    /// The queue index to play after `index` when a track ends by itself, if there is one
+    fn next_index(&self, index: usize, queue_len: usize) -> Option<usize> {
+        let next = match *self.settings.repeat_mode.lock().unwrap() {
+            RepeatMode::Off => index + 1,
+            RepeatMode::Queue if index + 1 >= queue_len => 0,
+            RepeatMode::Queue => index + 1,
+            RepeatMode::Single => index,
+        };
+
+        (next < queue_len).then_some(next)
+    }
+
+    /// Where `track` should leave its end when it fades out, if it should fade into the next track
+    fn crossfade_handover(&self, track: &Track) -> Option<Handover> {
+        let queue_index = self.queue_index.lock().unwrap();
+        let queue = self.queue.lock().unwrap();
+        let next_index = self.next_index(*queue_index, queue.len());
+        let preload = self.preload.lock().unwrap();
+        let preload = preload
+            .as_ref()
+            .filter(|preload| preload.is_next(next_index, &queue))?;
+
+        // Albums play as they were mastered, which is often gapless or with their own transitions
+        if track.same_album(&queue[preload.index]) {
+            return None;
+        }
+
+        Some(preload.handover.clone())
+    }
+
+    /// Stops playback, including the preloaded track
+    fn stop(&self) {
+        self.sink.stop();
+        *self.preload.lock().unwrap() = None;
+        self.position.store(0, Ordering::Relaxed);
+    }
+
+    /// How far into the current track playback is
+    fn position(&self) -> Duration {
+        Duration::from_millis(self.position.load(Ordering::Relaxed))
+    }
+}
+
+/// A track added to the [`Sink`] before the one playing ends, so there's no gap between them
+struct Preload {
+    /// The queue index the track was loaded for
+    index: usize,
+    path: PathBuf,
+    /// Whether the file could be opened, since ones that couldn't are skipped instead
+    loaded: bool,
+    /// Set if the track shouldn't play after all, which ends it before it starts
+    cancelled: Arc<AtomicBool>,
+    /// Where the current track leaves its end if it fades into this one
+    handover: Handover,
+}
+
+impl Preload {
+    /// Whether this is still the track that should come next
+    ///
+    /// Queue edits can move a different track into the same index, so the path is checked too.
+    fn is_next(&self, next_index: Option<usize>, queue: &[Track]) -> bool {
+        next_index == Some(self.index)
+            && queue
+                .get(self.index)
+                .is_some_and(|track| track.path == self.path)
+    }
 }
 
 struct SearchState<T: Sync + Send + 'static> {
     matcher: Nucleo<T>,
     injector: Injector<T>,
     columns_to_search: Vec<CachedField>,
+    /// Criteria on audio properties parsed from the query, applied on top of the fuzzy match
+    filters: Vec<TrackFilter>,
     results: Vec<T>,
 }
 
 impl<T: Sync + Send + 'static> SearchState<T> {
     fn new() -> Self {
-        let columns_to_search = vec![CachedField::Title, CachedField::Artist, CachedField::Album];
+        let columns_to_search = vec![
+            CachedField::Title,
+            CachedField::Artist,
+            CachedField::Album,
+            CachedField::AlbumArtist,
+            CachedField::Genre,
+        ];
         let matcher = Nucleo::new(
             nucleo::Config::DEFAULT,
             Arc::new(|| {}),
@@ -176,19 +379,435 @@ impl<T: Sync + Send + 'static> SearchState<T> {
             matcher,
             injector,
             columns_to_search,
+            filters: Vec::new(),
             results,
         }
     }
 }
 
+impl SearchState<Track> {
+    /// Adds a [`Track`] to the items being searched
+    fn push(&self, track: Track) {
+        self.injector.push(track, |track, utf32_strings| {
+            for (index, column) in self.columns_to_search.iter().enumerate() {
+                utf32_strings[index] = track.cached_field_string(column).into();
+            }
+        });
+    }
+
+    /// Updates the matcher and filters from a search query
+    ///
+    /// Terms that parse as a [`TrackFilter`] are applied as filters, and the rest of the
+    /// query is fuzzy matched.
+    fn set_query(&mut self, query: &str) {
+        let (words, filters) = TrackFilter::parse_query(query);
+        self.filters = filters;
+        let text = words.join(" ");
+
+        // Note that this is NOT compatible with the upstream `nucleo` library behavior, and
+        // instead relies on a fork that OR's matches together
+        // See https://github.com/helix-editor/nucleo/issues/23#issuecomment-2643833781
+        // and https://github.com/helix-editor/nucleo/pull/53 for details
+        for column in 0..self.columns_to_search.len() {
+            self.matcher.pattern.reparse(
+                column,
+                &text,
+                CaseMatching::Ignore,
+                Normalization::Smart,
+                false,
+            );
+        }
+    }
+
+    /// Collects the tracks that currently match the query
+    fn update_results(&mut self) {
+        let items = self.matcher.snapshot().matched_items(..);
+        self.results = items
+            .map(|item| item.data)
+            .filter(|track| self.filters.iter().all(|filter| filter.matches(track)))
+            .cloned()
+            .collect();
+    }
+
+    /// Replaces all items being searched, e.g., after tracks have been edited
+    ///
+    /// `nucleo` doesn't support removing or updating individual items, so we have to start over.
+    fn replace_items(&mut self, tracks: &[Track]) {
+        self.matcher.restart(true);
+        self.injector = self.matcher.injector();
+        for track in tracks {
+            self.push(track.clone());
+        }
+    }
+}
+
+#[derive(Default)]
+/// State for browsing the library by artist
+struct ArtistBrowser {
+    artists: Vec<String>,
+    artist_list_state: ListState,
+    /// The artist whose tracks are being shown
+    artist: String,
+    tracks: Vec<Track>,
+    table_state: TableState,
+    scrollbar_state: ScrollbarState,
+}
+
+#[derive(Default)]
+/// The rows of the library table that have been read from the library database
+///
+/// Only the rows around the selection are read, in the order the library is sorted in, so the
+/// whole library doesn't have to be sorted to show it.
+struct LibraryPage {
+    /// Number of tracks in the library
+    len: usize,
+    /// Row of the first track in `tracks`
+    start: usize,
+    tracks: Vec<Track>,
+}
+
+impl LibraryPage {
+    fn get(&self, row: usize) -> Option<&Track> {
+        row.checked_sub(self.start)
+            .and_then(|index| self.tracks.get(index))
+    }
+
+    fn contains(&self, rows: Range<usize>) -> bool {
+        self.start <= rows.start && rows.end <= self.start + self.tracks.len()
+    }
+}
+
+/// State for the track details popup
+struct TrackDetailsPopup {
+    lines: Vec<Line<'static>>,
+    scroll: u16,
+}
+
+impl TrackDetailsPopup {
+    fn new(track: &Track) -> Self {
+        let heading = |text: &str| Line::from(text.to_owned()).bold();
+        let entry = |name: &str, value: String| {
+            Line::from(vec![
+                Span::raw(format!("{name: <24}")).bold(),
+                Span::raw(value),
+            ])
+        };
+
+        let mut lines = vec![
+            heading("File"),
+            entry("Path", track.path.display().to_string()),
+        ];
+
+        match track.details() {
+            Ok(details) => {
+                let properties = &details.properties;
+                let optional =
+                    |value: Option<String>| value.unwrap_or_else(|| "Unknown".to_owned());
+
+                lines.push(entry("Size", Self::format_size(details.file_size)));
+                lines.push(Line::raw(""));
+                lines.push(heading("Audio"));
+                lines.push(entry(
+                    "Codec",
+                    Track::codec_name(&details.file_type, properties),
+                ));
+                lines.push(entry(
+                    "Duration",
+                    format!("{:.3}s", properties.duration().as_secs_f64()),
+                ));
+                lines.push(entry(
+                    "Bitrate",
+                    optional(properties.audio_bitrate().map(|b| format!("{b} kbps"))),
+                ));
+                lines.push(entry(
+                    "Overall Bitrate",
+                    optional(properties.overall_bitrate().map(|b| format!("{b} kbps"))),
+                ));
+                lines.push(entry(
+                    "Sample Rate",
+                    optional(properties.sample_rate().map(|r| format!("{r} Hz"))),
+                ));
+                lines.push(entry(
+                    "Bit Depth",
+                    optional(properties.bit_depth().map(|d| format!("{d} bits"))),
+                ));
+                lines.push(entry(
+                    "Channels",
+                    optional(properties.channels().map(|c| c.to_string())),
+                ));
+
+                for (tag_type, items) in &details.tags {
+                    lines.push(Line::raw(""));
+                    lines.push(heading(&format!("{tag_type:?} Tag")));
+                    if items.is_empty() {
+                        lines.push(Line::raw("No items"));
+                    }
+                    for (field, value) in items {
+                        lines.push(entry(&field.name(), value.clone()));
+                    }
+                }
+
+                lines.push(Line::raw(""));
+                lines.push(heading("Pictures"));
+                if details.pictures.is_empty() {
+                    lines.push(Line::raw("No embedded pictures"));
+                }
+                for (picture_type, mime_type, size) in &details.pictures {
+                    lines.push(entry(
+                        &format!("{picture_type:?}"),
+                        format!(
+                            "{}, {}",
+                            mime_type.as_deref().unwrap_or("unknown format"),
+                            Self::format_size(*size as u64)
+                        ),
+                    ));
+                }
+            }
+            Err(e) => {
+                lines.push(Line::raw(""));
+                lines.push(Line::from(format!("Couldn't read file: {e}")).red());
+            }
+        }
+
+        if !track.inferred.is_empty() {
+            lines.push(Line::raw(""));
+            lines.push(heading("Inferred from Path"));
+            for field in &track.inferred {
+                lines.push(entry(field.name(), track.cached_field_string(field)));
+            }
+        }
+
+        Self { lines, scroll: 0 }
+    }
+
+    fn format_size(bytes: u64) -> String {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            format!("{bytes} B")
+        } else {
+            format!("{size:.1} {} ({bytes} bytes)", UNITS[unit])
+        }
+    }
+}
+
+/// State for the tag editing popup
+struct TagEditor<'a> {
+    /// Tracks being edited
+    tracks: Vec<Track>,
+    fields: Vec<TagEditorField<'a>>,
+    selected_field: usize,
+    error: Option<String>,
+}
+
+/// An editable field in the tag editor
+struct TagEditorField<'a> {
+    field: CachedField,
+    input: TextArea<'a>,
+    /// Value the field started out with, which is empty if the tracks' values differ
+    initial: String,
+    /// Set when the field is explicitly cleared, since an empty input on its own means
+    /// "leave unchanged" for fields with differing values
+    cleared: bool,
+}
+
+impl TagEditor<'_> {
+    const FIELDS: [CachedField; 6] = [
+        CachedField::Title,
+        CachedField::Artist,
+        CachedField::Album,
+        CachedField::Year,
+        CachedField::Genre,
+        CachedField::TrackNumber,
+    ];
+
+    /// Placeholder for fields whose values differ between the tracks being edited
+    const MULTIPLE_VALUES: &'static str = "<multiple values>";
+
+    fn new(tracks: Vec<Track>) -> Self {
+        let fields = Self::FIELDS
+            .iter()
+            .map(|field| {
+                let mut values = tracks.iter().map(|track| track.cached_field_string(field));
+                let first = values.next().unwrap_or_default();
+                let shared = values.all(|value| value == first);
+
+                // For batch edits with differing values, start empty and keep the existing values
+                // unless something is typed in
+                let initial = if shared { first } else { String::new() };
+                let mut input = TextArea::new(vec![initial.clone()]);
+                input.move_cursor(tui_textarea::CursorMove::End);
+                if !shared {
+                    input.set_placeholder_text(Self::MULTIPLE_VALUES);
+                }
+
+                TagEditorField {
+                    field: *field,
+                    input,
+                    initial,
+                    cleared: false,
+                }
+            })
+            .collect();
+
+        Self {
+            tracks,
+            fields,
+            selected_field: 0,
+            error: None,
+        }
+    }
+
+    /// Fields whose values have been changed, along with their new values
+    fn edits(&self) -> Vec<(CachedField, String)> {
+        self.fields
+            .iter()
+            .map(|field| (field, field.input.lines().join("")))
+            .filter(|(field, value)| *value != field.initial || field.cleared && value.is_empty())
+            .map(|(field, value)| (field.field, value))
+            .collect()
+    }
+
+    /// Empties the selected field and marks it to be removed from every track
+    fn clear_selected_field(&mut self) {
+        let field = &mut self.fields[self.selected_field];
+        field.input = TextArea::default();
+        field.input.set_placeholder_text("<cleared>");
+        field.cleared = true;
+    }
+
+    /// Passes a key press on to the selected field's input
+    fn input(&mut self, key_event: KeyEvent) {
+        let field = &mut self.fields[self.selected_field];
+        if field.input.input(key_event) && field.cleared {
+            field.cleared = false;
+            if field.initial.is_empty() {
+                field.input.set_placeholder_text(Self::MULTIPLE_VALUES);
+            }
+        }
+    }
+}
+
+/// State for the popup that writes tags parsed from track paths
+struct AutoTagger<'a> {
+    /// Each track along with the library root it's in
+    tracks: Vec<(Track, PathBuf)>,
+    input: TextArea<'a>,
+    /// Tracks that would change with the current template, along with their changes
+    preview: Vec<(Track, Vec<TagChange>)>,
+    scroll: u16,
+    error: Option<String>,
+}
+
+impl AutoTagger<'_> {
+    /// Starts out with `template` if given, e.g., the first of the configured path templates
+    fn new(tracks: Vec<(Track, PathBuf)>, template: Option<&str>) -> Self {
+        let mut input = TextArea::new(vec![template.unwrap_or_default().to_owned()]);
+        input.move_cursor(tui_textarea::CursorMove::End);
+        input.set_placeholder_text("{artist}/{album}/{track} - {title}");
+
+        let mut tagger = Self {
+            tracks,
+            input,
+            preview: Vec::new(),
+            scroll: 0,
+            error: None,
+        };
+        tagger.update_preview();
+        tagger
+    }
+
+    fn update_preview(&mut self) {
+        self.scroll = 0;
+        self.preview.clear();
+        self.error = None;
+
+        let template = self.input.lines().join("");
+        if template.is_empty() {
+            return;
+        }
+        match PathTemplate::parse(&template) {
+            Ok(template) => {
+                self.preview = self
+                    .tracks
+                    .iter()
+                    .map(|(track, root)| {
+                        let changes = autotag::tag_changes(&template, root, track);
+                        (track.clone(), changes)
+                    })
+                    .filter(|(_, changes)| !changes.is_empty())
+                    .collect();
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+}
+
+/// State for the popup that jumps to a time in the playing track
+struct SeekPrompt<'a> {
+    input: TextArea<'a>,
+    error: Option<String>,
+}
+
+impl SeekPrompt<'_> {
+    fn new() -> Self {
+        let mut input = TextArea::default();
+        input.set_placeholder_text("mm:ss");
+
+        Self { input, error: None }
+    }
+}
+
+/// State for the popup that saves the queue as a playlist and queues saved playlists
+struct PlaylistPicker<'a> {
+    names: Vec<String>,
+    list_state: ListState,
+    /// Name to save the queue under, while it's being typed
+    input: Option<TextArea<'a>>,
+}
+
+impl PlaylistPicker<'_> {
+    fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            list_state: ListState::default().with_selected(Some(0)),
+            input: None,
+        }
+    }
+
+    fn start_naming(&mut self) {
+        let mut input = TextArea::default();
+        input.set_placeholder_text("Playlist name");
+        self.input = Some(input);
+    }
+}
+
 struct Model<'a> {
     player_state: PlayerState,
+    /// Every track in the library, for searching and scanning
+    ///
+    /// This stays empty when the library is kept in the database, which is queried instead.
     tracks: Vec<Track>,
+    /// What the library table shows with the library database, or when only one root is shown,
+    /// instead of `tracks`
+    library_page: Option<LibraryPage>,
+    /// Label of the library root whose tracks the library table is limited to
+    root_filter: Option<String>,
     playback_state: PlaybackState,
     volume_percentage: usize,
 
     // UI related state
     theme: Theme,
+    library_columns: Vec<CachedField>,
+    library_sort: Vec<SortKey>,
+    collation: Collation,
+    sort_picker_state: ListState,
     library_table_state: TableState,
     library_scrollbar_state: ScrollbarState,
     search_bar: TextArea<'a>,
@@ -197,12 +816,37 @@ struct Model<'a> {
     sidebar_table_state: TableState,
     sidebar_scrollbar_state: ScrollbarState,
     image_state: Arc<Mutex<Option<StatefulProtocol>>>,
+    cover_art_patterns: Vec<Pattern>,
+    scan_options: ScanOptions,
+    /// How far to skip when seeking forward or back
+    seek_step: Duration,
     last_track_focus_update: Instant,
     needs_image_redraw: bool,
 
     search_state: SearchState<Track>,
+    artist_browser: ArtistBrowser,
+    /// The queue in the order it was added in, while it's shuffled
+    unshuffled_queue: Option<Vec<Track>>,
+    /// Tracks selected for batch operations, e.g., tag editing
+    marked_tracks: HashSet<Track>,
+    tag_editor: Option<TagEditor<'a>>,
+    auto_tagger: Option<AutoTagger<'a>>,
+    seek_prompt: Option<SeekPrompt<'a>>,
+    playlist_picker: Option<PlaylistPicker<'a>>,
+    track_details: Option<TrackDetailsPopup>,
+    /// Message shown in the status bar until the next key press
+    status: Option<String>,
+    /// Set while the library is being scanned in the background
+    scan_progress: Option<ScanProgress>,
+    /// Files and directories changed while a scan is running, which the scan's results may be
+    /// out of date for
+    changed_during_scan: Vec<PathBuf>,
+    /// The last track that started playing, for recording play history
+    last_played: Option<PathBuf>,
 
     // Resources
+    /// Set when the library is stored in a database rather than `library.csv`
+    database: Option<LibraryDatabase>,
     picker: Picker,
     // We need to hold the stream to prevent it from being dropped, even if we don't access it otherwise
     // See https://github.com/RustAudio/rodio/issues/525
@@ -223,10 +867,19 @@ impl Model<'_> {
         Ok(Self {
             player_state: PlayerState::default(),
             tracks: Vec::new(),
+            library_page: None,
+            root_filter: None,
             playback_state,
             volume_percentage: 50,
 
             theme: Theme::default(),
+            library_columns: Config::default().library_columns,
+            library_sort: SortKey::album_order(),
+            collation: Collation::default(),
+            cover_art_patterns: Vec::new(),
+            scan_options: ScanOptions::default(),
+            seek_step: Duration::from_secs(Config::default().seek_step),
+            sort_picker_state: ListState::default(),
             library_table_state: TableState::default().with_selected(0),
             library_scrollbar_state: ScrollbarState::new(0),
             search_bar: TextArea::default(),
@@ -240,7 +893,20 @@ impl Model<'_> {
             needs_image_redraw: true,
 
             search_state,
-
+            artist_browser: ArtistBrowser::default(),
+            unshuffled_queue: None,
+            marked_tracks: HashSet::new(),
+            tag_editor: None,
+            auto_tagger: None,
+            seek_prompt: None,
+            playlist_picker: None,
+            track_details: None,
+            status: None,
+            scan_progress: None,
+            changed_during_scan: Vec::new(),
+            last_played: None,
+
+            database: None,
             picker,
             _stream: stream_handle,
         })
@@ -248,10 +914,52 @@ impl Model<'_> {
 
     fn from_config(config: &Config) -> Result<Self> {
         let mut model = Self::new()?;
-        model.theme = Theme::get_theme_by_name(&config.theme)
-            .unwrap_or_else(|_| panic!("Error while loading theme '{}'", config.theme));
+        let theme_path = config.theme_path()?;
+        model.theme = Theme::load_from_file(&theme_path).wrap_err_with(|| {
+            format!(
+                "Couldn't load the theme {} set by theme in the config file",
+                theme_path.display()
+            )
+        })?;
 
         model.playback_state.settings.show_track_art = config.show_track_art;
+        model
+            .playback_state
+            .settings
+            .crossfade
+            .store(config.crossfade, Ordering::Relaxed);
+        model.playback_state.settings.crossfade_duration =
+            Duration::from_secs(config.crossfade_duration);
+        model.playback_state.settings.speed = SpeedControl::new(
+            config.preserve_pitch,
+            Arc::downgrade(&model.playback_state.sink),
+        );
+        // Playing everything at normal speed is better than not starting at all, but the file
+        // isn't overwritten so the speeds in it can still be recovered
+        if let Some(file) = paths::playback_speeds_file() {
+            let saved_speeds = SavedSpeeds::load(file.clone()).unwrap_or_else(|e| {
+                model.status = Some(format!(
+                    "Couldn't load saved playback speeds from {}, so new ones won't be saved \
+                        until it's fixed: {e}",
+                    file.display()
+                ));
+                SavedSpeeds::unreadable(file)
+            });
+            *model.playback_state.settings.saved_speeds.lock().unwrap() = saved_speeds;
+        }
+        model.library_columns = config.library_columns.clone();
+        model.library_sort = config.library_sort.clone();
+        model.collation = Collation {
+            ignored_articles: config.ignored_articles.clone(),
+        };
+        model.database = library::open_database(config)?;
+        model.cover_art_patterns = config
+            .cover_art_patterns
+            .iter()
+            .map(|pattern| Pattern::new(pattern))
+            .collect::<Result<_, _>>()?;
+        model.scan_options = ScanOptions::from_config(config)?;
+        model.seek_step = Duration::from_secs(config.seek_step);
 
         Ok(model)
     }
@@ -261,8 +969,100 @@ impl Model<'_> {
         match message {
             Message::Quit => self.player_state.quit = true,
             Message::ToggleHelp => self.player_state.show_help = !self.player_state.show_help,
+            Message::ToggleSortPicker => {
+                self.player_state.show_sort_picker = !self.player_state.show_sort_picker;
+                self.sort_picker_state.select(Some(0));
+            }
+            Message::SelectSortPickerRow(row) => self.sort_picker_state.select(Some(row)),
+            Message::ToggleMark(track) => {
+                if !self.marked_tracks.remove(&track) {
+                    self.marked_tracks.insert(track);
+                }
+            }
+            Message::ClearMarks => self.marked_tracks.clear(),
+            Message::OpenTagEditor(tracks) => {
+                if !tracks.is_empty() {
+                    self.tag_editor = Some(TagEditor::new(tracks));
+                }
+            }
+            Message::CloseTagEditor => self.tag_editor = None,
+            Message::OpenAutoTagger(tracks) => {
+                // Tracks outside of the library roots have no path to match the template against
+                let tracks: Vec<_> = tracks
+                    .into_iter()
+                    .filter_map(|track| {
+                        let root = self.scan_options.root_of(&track.path)?.path.clone();
+                        Some((track, root))
+                    })
+                    .collect();
+                if !tracks.is_empty() {
+                    let template = self.scan_options.templates.first();
+                    self.auto_tagger =
+                        Some(AutoTagger::new(tracks, template.map(PathTemplate::as_str)));
+                }
+            }
+            Message::CloseAutoTagger => self.auto_tagger = None,
+            Message::ScrollAutoTagger(scroll) => {
+                if let Some(tagger) = &mut self.auto_tagger {
+                    tagger.scroll = scroll;
+                }
+            }
+            Message::SaveAutoTags => self.save_auto_tags(),
+            Message::ShowTrackDetails(track) => {
+                self.track_details = Some(TrackDetailsPopup::new(&track));
+            }
+            Message::CloseTrackDetails => self.track_details = None,
+            Message::ScrollTrackDetails(scroll) => {
+                if let Some(details) = &mut self.track_details {
+                    details.scroll = scroll;
+                }
+            }
+            Message::SelectTagEditorField(index) => {
+                if let Some(editor) = &mut self.tag_editor {
+                    editor.selected_field = index;
+                }
+            }
+            Message::SaveTagEdits => self.save_tag_edits(),
+            Message::SortLibrary(sort) => {
+                self.library_sort = sort;
+                self.sort_library();
+                // Only persist the sort, since other settings may have been overridden by arguments
+                if let Some(path) = paths::config_file()
+                    && path.exists()
+                {
+                    let _ = Config::save_library_sort(&path, &self.library_sort);
+                }
+            }
             Message::SelectLibraryRow(row) => self.select_library_row(row),
+            Message::CycleRootFilter => self.cycle_root_filter(),
             Message::SelectSearchResultRow(row) => self.select_search_results_row(row),
+            Message::ShowArtists => {
+                self.player_state.main_panel_view = MainPanelView::Artists;
+                // Rebuild every time in case tags have been edited
+                self.artist_browser.artists = self.artists();
+                if self.artist_browser.artist_list_state.selected().is_none() {
+                    self.artist_browser.artist_list_state.select(Some(0));
+                }
+            }
+            Message::SelectArtistRow(row) => {
+                self.artist_browser.artist_list_state.select(Some(row))
+            }
+            Message::ShowArtistTracks(artist) => {
+                let tracks = self.artist_tracks(&artist);
+
+                self.artist_browser.scrollbar_state = ScrollbarState::new(tracks.len());
+                self.artist_browser.table_state = TableState::default().with_selected(0);
+                self.artist_browser.tracks = tracks;
+                self.artist_browser.artist = artist;
+                self.player_state.main_panel_view = MainPanelView::ArtistTracks;
+                self.request_image_redraw();
+            }
+            Message::SelectArtistTrackRow(row) => {
+                self.artist_browser.table_state.select(Some(row));
+                self.artist_browser.scrollbar_state =
+                    self.artist_browser.scrollbar_state.position(row);
+                self.request_image_redraw();
+            }
             Message::SelectSidebarQueueRow(row) => self.select_sidebar_row(row),
             Message::FocusLibrary => {
                 self.player_state.main_panel_view = MainPanelView::Library;
@@ -287,16 +1087,18 @@ impl Model<'_> {
                 self.search_bar = TextArea::default();
                 self.search_state.results = Vec::new();
                 self.search_results_table_state = TableState::default().with_selected(0);
-                self.search_results_scrollbar_state = ScrollbarState::new(self.tracks.len());
-                for column in 0..self.search_state.columns_to_search.len() {
-                    self.search_state.matcher.pattern.reparse(
-                        column,
-                        "",
-                        CaseMatching::Ignore,
-                        Normalization::Smart,
-                        false,
-                    );
+                self.search_results_scrollbar_state = ScrollbarState::new(0);
+                self.search_state.set_query("");
+            }
+
+            Message::ClearStatus => self.status = None,
+            Message::LibraryChanged(paths) => self.apply_library_changes(&paths),
+            Message::Scan(events) => self.handle_scan_events(events),
+            Message::TrackStarted(path) => {
+                if let Some(database) = &self.database {
+                    let _ = database.record_play(&path, SystemTime::now());
                 }
+                self.last_played = Some(path);
             }
 
             Message::ShowSearchResults => {
@@ -315,6 +1117,17 @@ impl Model<'_> {
             Message::CycleRepeatMode => {
                 self.cycle_repeat_mode();
             }
+            Message::SpeedUp => {
+                self.set_speed(self.playback_state.settings.speed.get() + speed::SPEED_STEP);
+            }
+            Message::SpeedDown => {
+                self.set_speed(self.playback_state.settings.speed.get() - speed::SPEED_STEP);
+            }
+            Message::ResetSpeed => self.set_speed(1.0),
+            Message::SaveSpeed(scope) => self.save_speed(scope),
+            Message::CycleShuffleMode => {
+                self.cycle_shuffle_mode();
+            }
             Message::PlayPause => {
                 let sink = &self.playback_state.sink;
                 if sink.is_paused() {
@@ -323,22 +1136,82 @@ impl Model<'_> {
                     sink.pause();
                 }
             }
+            Message::ToggleCrossfade => {
+                let settings = &self.playback_state.settings;
+                let enabled = !settings.crossfade.fetch_xor(true, Ordering::Relaxed);
+                self.status = Some(if enabled {
+                    format!("Crossfade on ({}s)", settings.crossfade_duration.as_secs())
+                } else {
+                    "Crossfade off".to_owned()
+                });
+            }
             Message::PrevTrack => self.previous_track(),
             Message::NextTrack => self.next_track(),
+            Message::SeekForward => {
+                let position = self.playback_state.position() + self.seek_step;
+                self.seek(position);
+            }
+            Message::SeekBackward => {
+                let position = self
+                    .playback_state
+                    .position()
+                    .saturating_sub(self.seek_step);
+                self.seek(position);
+            }
+            Message::RestartTrack => self.restart_track(),
+            Message::OpenSeekPrompt => {
+                if self.now_playing().is_some() {
+                    self.seek_prompt = Some(SeekPrompt::new());
+                }
+            }
+            Message::CloseSeekPrompt => self.seek_prompt = None,
+            Message::SubmitSeekPrompt => {
+                let Some(prompt) = &mut self.seek_prompt else {
+                    return;
+                };
+                let text = prompt.input.lines().join("");
+                match Track::parse_duration(&text) {
+                    Some(position) => {
+                        self.seek_prompt = None;
+                        self.seek(position);
+                    }
+                    None => prompt.error = Some(format!("Not a time: '{text}'. Try, e.g., 1:30")),
+                }
+            }
+            Message::ChangeRating(track, change) => self.change_rating(&track, change),
+            Message::OpenPlaylists => self.open_playlists(),
+            Message::ClosePlaylists => self.playlist_picker = None,
+            Message::SelectPlaylistRow(row) => {
+                if let Some(picker) = &mut self.playlist_picker {
+                    picker.list_state.select(Some(row));
+                }
+            }
+            Message::QueuePlaylist(name) => self.queue_playlist(&name),
+            Message::SavePlaylist(name) => self.save_playlist(&name),
+            Message::DeletePlaylist(name) => self.delete_playlist(&name),
             Message::QueueTrack(track) => {
-                self.queue_track(track.clone());
+                self.queue_track(track);
                 if self.playback_state.sink.empty() {
-                    Self::play_track(&track, &self.playback_state);
+                    self.play_current();
                 }
             }
             Message::QueueTrackNext(track) => {
                 let index = *self.playback_state.queue_index.lock().unwrap();
+                let mut queue = self.playback_state.queue.lock().unwrap();
                 let mut offset = self.playback_state.insertion_offset.lock().unwrap();
                 *offset += 1;
 
-                let mut queue = self.playback_state.queue.lock().unwrap();
-
                 queue.insert(index + *offset, track.clone());
+                // Keep it next to the current track once the queue is unshuffled too
+                if let Some(unshuffled) = &mut self.unshuffled_queue {
+                    let position = queue
+                        .get(index)
+                        .and_then(|current| unshuffled.iter().position(|t| t.path == current.path))
+                        .map_or(unshuffled.len(), |position| {
+                            (position + *offset).min(unshuffled.len())
+                        });
+                    unshuffled.insert(position, track.clone());
+                }
 
                 self.sidebar_scrollbar_state =
                     self.sidebar_scrollbar_state.content_length(queue.len());
@@ -347,6 +1220,15 @@ impl Model<'_> {
                     Self::play_track(&track, &self.playback_state.clone());
                 }
             }
+            Message::QueueAlbum(track) => {
+                for track in self.album_tracks(&track) {
+                    self.queue_track(track);
+                }
+
+                if self.playback_state.sink.empty() {
+                    self.play_current();
+                }
+            }
             Message::RemoveFromQueue(index) => {
                 self.remove_track(index);
             }
@@ -355,63 +1237,794 @@ impl Model<'_> {
                     !self.playback_state.settings.show_track_art;
             }
         }
+
+        // Queue edits, skips and repeat mode changes can all change which track comes next
+        Self::preload_next(&self.playback_state);
     }
 
-    /// Signal that the track art display needs to be updated
+    /// Writes the changes in the tag editor to disk and updates the library with them
     ///
-    /// The player has an internal cooldown to only redraw after it detects scrolling has stopped
-    fn request_image_redraw(&mut self) {
-        self.last_track_focus_update = Instant::now();
-        self.needs_image_redraw = true;
-    }
+    /// The editor is kept open with an error message if any track couldn't be written.
+    fn save_tag_edits(&mut self) {
+        let Some(editor) = &mut self.tag_editor else {
+            return;
+        };
 
-    fn cycle_repeat_mode(&mut self) {
-        let mut repeat_mode = self.playback_state.settings.repeat_mode.lock().unwrap();
-        *repeat_mode = match *repeat_mode {
-            RepeatMode::Off => RepeatMode::Queue,
-            RepeatMode::Queue => RepeatMode::Single,
-            RepeatMode::Single => RepeatMode::Off,
+        let edits = editor.edits();
+        if edits.is_empty() {
+            self.tag_editor = None;
+            return;
         }
-    }
 
-    fn increment_volume(&mut self, percentage: usize) {
-        self.volume_percentage += percentage;
-        if self.volume_percentage > 100 {
-            self.volume_percentage = 100;
+        let mut updated = Vec::new();
+        let mut errors = Vec::new();
+        for track in &editor.tracks {
+            let mut track = track.clone();
+            match track.write_tags(&edits) {
+                Ok(()) => updated.push(track),
+                Err(e) => errors.push(format!("{}: {e}", track.path.display())),
+            }
         }
-        self.playback_state
-            .sink
-            .set_volume(self.volume_percentage as f32 / 100.0);
-    }
-
-    fn decrement_volume(&mut self, percentage: usize) {
-        self.volume_percentage = self.volume_percentage.saturating_sub(percentage);
-        self.playback_state
-            .sink
-            .set_volume(self.volume_percentage as f32 / 100.0);
-    }
 
-    /// Gets the currently playing [`Track`]
-    fn now_playing(&self) -> Option<Track> {
-        let queue_guard = self.playback_state.queue.lock().unwrap();
-        queue_guard
-            .get(*self.playback_state.queue_index.lock().unwrap())
-            .cloned()
-    }
+        if errors.is_empty() {
+            self.tag_editor = None;
+        } else {
+            editor.error = Some(errors.join("\n"));
+        }
 
-    fn select_library_row(&mut self, row: usize) {
-        self.library_table_state.select(Some(row));
-        self.library_scrollbar_state = self.library_scrollbar_state.position(row);
+        if updated.is_empty() {
+            return;
+        }
 
-        self.request_image_redraw();
+        for track in &updated {
+            self.replace_track(track);
+        }
+        self.mark_changed(updated.iter().map(|track| track.path.clone()));
+        self.save_library(&updated, &[]);
+        self.refresh_library();
     }
 
-    fn select_search_results_row(&mut self, row: usize) {
-        self.search_results_table_state.select(Some(row));
-        self.search_results_scrollbar_state = self.search_results_scrollbar_state.position(row);
-
-        self.request_image_redraw();
-    }
+    /// Writes the tags previewed in the auto tagger and updates the library with them
+    ///
+    /// Like the tag editor, it's kept open with an error message if any track couldn't be
+    /// written.
+    fn save_auto_tags(&mut self) {
+        let Some(tagger) = &mut self.auto_tagger else {
+            return;
+        };
+
+        let mut updated = Vec::new();
+        let mut errors = Vec::new();
+        for (track, changes) in &tagger.preview {
+            let mut track = track.clone();
+            match autotag::write_changes(&mut track, changes) {
+                Ok(()) => updated.push(track),
+                Err(e) => errors.push(format!("{}: {e}", track.path.display())),
+            }
+        }
+
+        if errors.is_empty() {
+            self.auto_tagger = None;
+        } else {
+            tagger.preview.clear();
+            tagger.error = Some(errors.join("\n"));
+        }
+
+        if updated.is_empty() {
+            return;
+        }
+
+        for track in &updated {
+            self.replace_track(track);
+        }
+        self.mark_changed(updated.iter().map(|track| track.path.clone()));
+        self.save_library(&updated, &[]);
+        self.refresh_library();
+        self.status = Some(format!("Wrote tags to {} tracks", updated.len()));
+    }
+
+    /// Reads the library from the configured store
+    fn load_library(&self) -> Result<Cache> {
+        library::load_library(self.database.as_ref(), &self.library_sort)
+    }
+
+    /// Searches the library for the query typed into the search bar
+    ///
+    /// Without the database, the query is fuzzy matched against the tracks in memory, and the
+    /// results are collected as the matcher catches up. The database is queried straight away
+    /// instead, and an empty query shows nothing rather than reading the whole library.
+    fn search(&mut self, query: &str) {
+        let Some(database) = &self.database else {
+            self.search_state.set_query(query);
+            self.search_state.update_results();
+            return;
+        };
+
+        let (words, filters) = TrackFilter::parse_query(query);
+        self.search_state.results = if words.is_empty() && filters.is_empty() {
+            Vec::new()
+        } else {
+            database
+                .search(&words, &filters, &self.library_sort)
+                .unwrap_or_default()
+        };
+    }
+
+    /// Every track in the library, read from the database if it's kept there
+    fn all_tracks(&self) -> Result<Vec<Track>> {
+        match &self.database {
+            Some(database) => database.tracks(&self.library_sort),
+            None => Ok(self.tracks.clone()),
+        }
+    }
+
+    /// Writes tracks that were added, changed or removed to the configured store
+    ///
+    /// Only those tracks are written to the database, while `library.csv` is rewritten from
+    /// `tracks`. Errors are ignored, since the library can always be rebuilt by scanning.
+    fn save_library(&mut self, updated: &[Track], removed: &[PathBuf]) {
+        let _ = match &mut self.database {
+            Some(database) => database
+                .upsert_tracks(updated)
+                .and_then(|()| database.remove_tracks(removed)),
+            None => library::save_library(None, &self.tracks),
+        };
+    }
+
+    /// Updates the views of the library after the tracks have changed, keeping the selection
+    fn refresh_library(&mut self) {
+        self.sort_library();
+        if self.database.is_none() {
+            self.search_state.replace_items(&self.tracks);
+        }
+        self.library_scrollbar_state = self
+            .library_scrollbar_state
+            .content_length(self.library_len());
+    }
+
+    /// Shows tracks from a background scan as they come in
+    ///
+    /// New tracks are added to the end of the library, and it's sorted once the scan finishes.
+    /// With the library database, the tracks that were read are written to it together for
+    /// each batch of events.
+    fn handle_scan_events(&mut self, events: Vec<ScanEvent>) {
+        let in_database = self.database.is_some();
+        let mut scanned = Vec::new();
+        for event in events {
+            let Some(progress) = &mut self.scan_progress else {
+                return;
+            };
+
+            match event {
+                ScanEvent::Found => progress.found += 1,
+                ScanEvent::Unchanged => progress.unchanged += 1,
+                ScanEvent::Added(track) | ScanEvent::Updated(track) if in_database => {
+                    progress.parsed += 1;
+                    scanned.push(track);
+                }
+                ScanEvent::Added(track) => {
+                    progress.parsed += 1;
+                    self.search_state.push(track.clone());
+                    self.tracks.push(track);
+                    self.library_scrollbar_state = self
+                        .library_scrollbar_state
+                        .content_length(self.library_len());
+                }
+                ScanEvent::Updated(track) => {
+                    progress.parsed += 1;
+                    self.replace_track(&track);
+                }
+                ScanEvent::Failed => progress.failed += 1,
+                ScanEvent::Finished(tracks, summary) => {
+                    self.finish_scan(tracks, &summary);
+                    // Every track from the scan has been written by now
+                    scanned.clear();
+                }
+            }
+        }
+
+        // Tracks changed since the scan started are newer than what it read
+        scanned.retain(|track| {
+            !self
+                .changed_during_scan
+                .iter()
+                .any(|path| track.path.starts_with(path))
+        });
+        if scanned.is_empty() {
+            return;
+        }
+        for track in &scanned {
+            self.replace_track(track);
+        }
+        self.save_library(&scanned, &[]);
+        self.refresh_library();
+    }
+
+    /// Brings the library up to date with a finished scan
+    ///
+    /// The scan's tracks replace the library, except for those changed while it was running.
+    fn finish_scan(&mut self, tracks: Vec<Track>, summary: &ScanSummary) {
+        self.scan_progress = None;
+        let changed = std::mem::take(&mut self.changed_during_scan);
+        match &mut self.database {
+            Some(database) => {
+                if let Ok(current) = database.tracks_under(&changed) {
+                    let _ = database.sync_tracks(&library::merge_scan(current, tracks, &changed));
+                }
+            }
+            None => {
+                self.tracks =
+                    library::merge_scan(std::mem::take(&mut self.tracks), tracks, &changed);
+                self.save_library(&[], &[]);
+            }
+        }
+        self.refresh_library();
+
+        // Don't hide problems reported while loading the cache, unless roots are missing
+        if summary.unavailable.is_empty() {
+            self.status
+                .get_or_insert_with(|| format!("Library scanned: {summary}"));
+        } else {
+            self.status = Some(format!("Library scanned: {summary}"));
+        }
+    }
+
+    /// Updates the library for files that changed on disk, keeping the selection and queue
+    ///
+    /// With the library database, only the tracks under the changed paths are read from it and
+    /// written back.
+    fn apply_library_changes(&mut self, paths: &[PathBuf]) {
+        let (rescanned, removed, summary) = match &self.database {
+            Some(database) => {
+                let Ok(mut tracks) = database.tracks_under(paths) else {
+                    return;
+                };
+                let previous: Vec<PathBuf> = tracks.iter().map(|t| t.path.clone()).collect();
+                let (rescanned, summary) =
+                    library::rescan_paths(&mut tracks, &self.scan_options, paths);
+                let remaining: HashSet<&Path> = tracks.iter().map(|t| t.path.as_path()).collect();
+                let removed = previous
+                    .into_iter()
+                    .filter(|path| !remaining.contains(path.as_path()))
+                    .collect();
+                (rescanned, removed, summary)
+            }
+            None => {
+                let (rescanned, summary) =
+                    library::rescan_paths(&mut self.tracks, &self.scan_options, paths);
+                (rescanned, Vec::new(), summary)
+            }
+        };
+        if summary == ScanSummary::default() {
+            return;
+        }
+
+        for track in &rescanned {
+            self.replace_track(track);
+        }
+        self.mark_changed(paths.iter().cloned());
+        self.save_library(&rescanned, &removed);
+        self.refresh_library();
+
+        self.status = Some(format!("Library updated: {summary}"));
+    }
+
+    /// Remembers paths whose tracks were changed while a scan is running, so the scan's results
+    /// don't replace them when it finishes
+    fn mark_changed(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        if self.scan_progress.is_some() {
+            self.changed_during_scan.extend(paths);
+        }
+    }
+
+    /// Replaces every copy of a [`Track`] in the library and queue with the given one
+    fn replace_track(&mut self, track: &Track) {
+        if self.marked_tracks.remove(track) {
+            self.marked_tracks.insert(track.clone());
+        }
+
+        let mut queue = self.playback_state.queue.lock().unwrap();
+        let copies = self
+            .tracks
+            .iter_mut()
+            .chain(
+                self.library_page
+                    .iter_mut()
+                    .flat_map(|page| page.tracks.iter_mut()),
+            )
+            .chain(queue.iter_mut())
+            .chain(self.search_state.results.iter_mut())
+            .chain(self.artist_browser.tracks.iter_mut())
+            .filter(|t| *t == track);
+        for copy in copies {
+            *copy = track.clone();
+        }
+    }
+
+    /// Tracks to apply a batch operation to: the marked tracks if there are any, otherwise the
+    /// given track
+    fn batch_tracks(&self, track: Track) -> Vec<Track> {
+        if self.marked_tracks.is_empty() {
+            return vec![track];
+        }
+
+        let mut tracks: Vec<Track> = self.marked_tracks.iter().cloned().collect();
+        tracks.sort_by_cached_key(|track| track.sort_key(&self.library_sort, &self.collation));
+        tracks
+    }
+
+    /// The [`Track`] in the selected row of the focused table, if any
+    fn focused_track(&self) -> Option<Track> {
+        match self.player_state {
+            PlayerState {
+                focus: PanelFocus::Sidebar,
+                ..
+            } => self.sidebar_table_state.selected().and_then(|index| {
+                self.playback_state
+                    .queue
+                    .lock()
+                    .unwrap()
+                    .get(index)
+                    .cloned()
+            }),
+            PlayerState {
+                main_panel_view: MainPanelView::Library,
+                ..
+            } => self
+                .library_table_state
+                .selected()
+                .and_then(|index| self.library_track(index)),
+            PlayerState {
+                main_panel_view: MainPanelView::SearchResults,
+                ..
+            } => self
+                .search_results_table_state
+                .selected()
+                .and_then(|index| self.search_state.results.get(index).cloned()),
+            PlayerState {
+                main_panel_view: MainPanelView::ArtistTracks,
+                ..
+            } => self
+                .artist_browser
+                .table_state
+                .selected()
+                .and_then(|index| self.artist_browser.tracks.get(index).cloned()),
+            _ => None,
+        }
+    }
+
+    /// Tracks on the same album as `track`, in album order
+    fn album_tracks(&self, track: &Track) -> Vec<Track> {
+        let order = SortKey::album_order();
+        if let Some(database) = &self.database {
+            return database.album_tracks(track, &order).unwrap_or_default();
+        }
+
+        let mut album: Vec<Track> = self
+            .tracks
+            .iter()
+            .filter(|t| t.same_album(track))
+            .cloned()
+            .collect();
+        album.sort_by_cached_key(|track| track.sort_key(&order, &self.collation));
+
+        album
+    }
+
+    /// Tracks by the given artist, in album order
+    fn artist_tracks(&self, artist: &str) -> Vec<Track> {
+        if let Some(database) = &self.database
+            && let Ok(tracks) = database.tracks_by_artist(artist, &SortKey::album_order())
+        {
+            return tracks;
+        }
+
+        let key = self.collation.key(artist);
+        let mut tracks: Vec<Track> = self
+            .tracks
+            .iter()
+            .filter(|t| t.artists().iter().any(|a| self.collation.key(a) == key))
+            .cloned()
+            .collect();
+        let order = SortKey::album_order();
+        tracks.sort_by_cached_key(|track| track.sort_key(&order, &self.collation));
+
+        tracks
+    }
+
+    /// Every artist in the library, including each artist on tracks with multiple artists
+    fn artists(&self) -> Vec<String> {
+        if let Some(database) = &self.database
+            && let Ok(artists) = database.artists()
+        {
+            return artists;
+        }
+
+        // Keyed once up front, rather than normalizing each name on every comparison
+        let mut artists: Vec<(String, String)> = self
+            .tracks
+            .iter()
+            .flat_map(|track| track.artists())
+            .map(|artist| (self.collation.key(artist), artist.clone()))
+            .collect();
+        artists.sort();
+        artists.dedup_by(|(a, _), (b, _)| a == b);
+
+        artists.into_iter().map(|(_, artist)| artist).collect()
+    }
+
+    /// Sorts the library by the current sort keys, keeping the same track selected
+    ///
+    /// With the library database, the library table reads the rows it shows from it instead.
+    fn sort_library(&mut self) {
+        let selected = self
+            .library_table_state
+            .selected()
+            .and_then(|index| self.library_track(index));
+
+        let root = self.root_filter.as_deref();
+        let database_len = self
+            .database
+            .as_ref()
+            .and_then(|database| database.track_count(root).ok());
+        self.library_page = match database_len {
+            Some(len) => Some(LibraryPage {
+                len,
+                ..Default::default()
+            }),
+            None => {
+                self.tracks.sort_by_cached_key(|track| {
+                    track.sort_key(&self.library_sort, &self.collation)
+                });
+                // Without the database, a single root's tracks are all read at once
+                root.map(|root| {
+                    let tracks: Vec<Track> = self
+                        .tracks
+                        .iter()
+                        .filter(|track| track.root.as_deref() == Some(root))
+                        .cloned()
+                        .collect();
+                    LibraryPage {
+                        len: tracks.len(),
+                        start: 0,
+                        tracks,
+                    }
+                })
+            }
+        };
+
+        let Some(track) = selected else {
+            return;
+        };
+        let row = match (&self.library_page, &self.database) {
+            (Some(_), Some(database)) => database
+                .track_position(&track.path, &self.library_sort, root)
+                .ok()
+                .flatten(),
+            (Some(page), None) => page.tracks.iter().position(|t| *t == track),
+            (None, _) => self.tracks.iter().position(|t| *t == track),
+        };
+        if let Some(row) = row {
+            self.select_library_row(row);
+        }
+    }
+
+    /// Number of rows in the library table
+    fn library_len(&self) -> usize {
+        self.library_page
+            .as_ref()
+            .map_or(self.tracks.len(), |page| page.len)
+    }
+
+    /// The [`Track`] in the given row of the library table
+    fn library_track(&self, row: usize) -> Option<Track> {
+        let Some(page) = &self.library_page else {
+            return self.tracks.get(row).cloned();
+        };
+        if let Some(track) = page.get(row) {
+            return Some(track.clone());
+        }
+
+        self.database
+            .as_ref()?
+            .tracks_page(&self.library_sort, self.root_filter.as_deref(), row, 1)
+            .ok()?
+            .pop()
+    }
+
+    /// Limits the library table to the next library root, or shows every root again after the
+    /// last one
+    fn cycle_root_filter(&mut self) {
+        let names: Vec<String> = self
+            .scan_options
+            .roots
+            .iter()
+            .map(LibraryRoot::name)
+            .collect();
+        self.root_filter = match &self.root_filter {
+            None => names.first().cloned(),
+            Some(current) => names
+                .iter()
+                .skip_while(|name| *name != current)
+                .nth(1)
+                .cloned(),
+        };
+        self.status = Some(match &self.root_filter {
+            Some(root) => format!("Showing songs from {root}"),
+            None => "Showing songs from every library root".to_owned(),
+        });
+
+        self.refresh_library();
+        if self
+            .library_table_state
+            .selected()
+            .is_none_or(|row| row >= self.library_len())
+        {
+            self.select_library_row(0);
+        }
+    }
+
+    /// Reads the rows of the library table that could be shown from the database, unless
+    /// they've been read already
+    fn load_library_page(&mut self, visible_rows: usize) {
+        let (Some(page), Some(database)) = (&mut self.library_page, &self.database) else {
+            return;
+        };
+
+        // The table scrolls to keep the selected row in view
+        let selected = self.library_table_state.selected().unwrap_or(0);
+        let offset = self.library_table_state.offset();
+        let first = offset.min(selected.saturating_sub(visible_rows));
+        let end = (offset.max(selected) + visible_rows).min(page.len);
+        if page.contains(first..end) {
+            return;
+        }
+
+        // Read a screen's worth either side too, so scrolling doesn't query on every frame
+        let start = first.saturating_sub(visible_rows);
+        let limit = end + visible_rows - start;
+        let root = self.root_filter.as_deref();
+        if let Ok(tracks) = database.tracks_page(&self.library_sort, root, start, limit) {
+            page.start = start;
+            page.tracks = tracks;
+        }
+    }
+
+    /// Sort keys for sorting the library by the given field, keeping albums in order otherwise
+    ///
+    /// If the library is already sorted by this field, the direction is reversed.
+    fn sort_keys_for_field(&self, field: CachedField) -> Vec<SortKey> {
+        let direction = match self.library_sort.first() {
+            Some(key) if key.field == field => key.direction.reversed(),
+            _ => SortDirection::Ascending,
+        };
+
+        let mut keys = vec![SortKey { field, direction }];
+        keys.extend(
+            SortKey::album_order()
+                .into_iter()
+                .filter(|k| k.field != field),
+        );
+        keys
+    }
+
+    /// Signal that the track art display needs to be updated
+    ///
+    /// The player has an internal cooldown to only redraw after it detects scrolling has stopped
+    fn request_image_redraw(&mut self) {
+        self.last_track_focus_update = Instant::now();
+        self.needs_image_redraw = true;
+    }
+
+    fn cycle_repeat_mode(&mut self) {
+        let mut repeat_mode = self.playback_state.settings.repeat_mode.lock().unwrap();
+        *repeat_mode = match *repeat_mode {
+            RepeatMode::Off => RepeatMode::Queue,
+            RepeatMode::Queue => RepeatMode::Single,
+            RepeatMode::Single => RepeatMode::Off,
+        }
+    }
+
+    /// Raises or lowers a track's rating by `change` half stars, out of 10
+    ///
+    /// A rating lowered to nothing is removed.
+    fn change_rating(&mut self, track: &Track, change: i8) {
+        let name = track.cached_field_string(&CachedField::Title);
+        // Ratings are only kept in the database
+        let Some(database) = &self.database else {
+            self.status = Some(Self::needs_database("Ratings"));
+            return;
+        };
+
+        let result = database.rating(&track.path).and_then(|rating| {
+            let rating = rating.unwrap_or(0).saturating_add_signed(change).min(10);
+            let rating = (rating > 0).then_some(rating);
+            database.set_rating(&track.path, rating).map(|()| rating)
+        });
+        self.status = Some(match result {
+            Ok(Some(rating)) => format!("Rated {name} {rating}/10"),
+            Ok(None) => format!("Removed the rating of {name}"),
+            Err(e) => format!("Couldn't rate {name}: {e}"),
+        });
+    }
+
+    /// Status message for something that's only kept in the library database, which isn't used
+    fn needs_database(what: &str) -> String {
+        format!("{what} are only kept with library_backend = \"sqlite\" in the config file")
+    }
+
+    /// Shows the saved playlists, which are only kept in the database
+    fn open_playlists(&mut self) {
+        let Some(database) = &self.database else {
+            self.status = Some(Self::needs_database("Playlists"));
+            return;
+        };
+
+        match database.playlists() {
+            Ok(names) => self.playlist_picker = Some(PlaylistPicker::new(names)),
+            Err(e) => self.status = Some(format!("Couldn't load playlists: {e}")),
+        }
+    }
+
+    fn queue_playlist(&mut self, name: &str) {
+        let Some(database) = &self.database else {
+            self.status = Some(Self::needs_database("Playlists"));
+            return;
+        };
+
+        match database.playlist_tracks(name) {
+            Ok(tracks) => {
+                self.playlist_picker = None;
+                self.status = Some(format!("Queued {} songs from {name}", tracks.len()));
+                for track in tracks {
+                    self.queue_track(track);
+                }
+                if self.playback_state.sink.empty() {
+                    self.play_current();
+                }
+            }
+            Err(e) => self.status = Some(format!("Couldn't load {name}: {e}")),
+        }
+    }
+
+    fn save_playlist(&mut self, name: &str) {
+        let Some(database) = &mut self.database else {
+            self.status = Some(Self::needs_database("Playlists"));
+            return;
+        };
+
+        let queue = self.playback_state.queue.lock().unwrap().clone();
+        match database.save_playlist(name, &queue) {
+            Ok(()) => {
+                self.playlist_picker = None;
+                self.status = Some(format!("Saved {} songs as {name}", queue.len()));
+            }
+            Err(e) => self.status = Some(format!("Couldn't save {name}: {e}")),
+        }
+    }
+
+    fn delete_playlist(&mut self, name: &str) {
+        let Some(database) = &self.database else {
+            self.status = Some(Self::needs_database("Playlists"));
+            return;
+        };
+
+        match database
+            .delete_playlist(name)
+            .and_then(|()| database.playlists())
+        {
+            Ok(names) => {
+                if let Some(picker) = &mut self.playlist_picker {
+                    let row = picker.list_state.selected().unwrap_or(0);
+                    picker
+                        .list_state
+                        .select(Some(row.min(names.len().saturating_sub(1))));
+                    picker.names = names;
+                }
+            }
+            Err(e) => self.status = Some(format!("Couldn't delete {name}: {e}")),
+        }
+    }
+
+    /// Shuffles the tracks after the current one with the next mode, or puts the queue back in
+    /// the order it was added in
+    fn cycle_shuffle_mode(&mut self) {
+        let mode = self.playback_state.settings.shuffle_mode.next();
+        self.playback_state.settings.shuffle_mode = mode;
+        self.status = Some(format!("Shuffle: {}", mode.name()));
+
+        // Play counts and ratings are only kept in the database
+        let stats = match (mode, &self.database) {
+            (ShuffleMode::Weighted, Some(database)) => database.play_stats().unwrap_or_else(|e| {
+                self.status = Some(format!("Couldn't load play counts: {e}"));
+                HashMap::new()
+            }),
+            _ => HashMap::new(),
+        };
+
+        let mut queue_index = self.playback_state.queue_index.lock().unwrap();
+        let mut queue = self.playback_state.queue.lock().unwrap();
+        // Each mode shuffles the original order, so the current track has to be found again
+        if let Some(unshuffled) = self.unshuffled_queue.take() {
+            let current = queue.get(*queue_index).map(|track| track.path.clone());
+            *queue = unshuffled;
+            if let Some(position) =
+                current.and_then(|current| queue.iter().position(|track| track.path == current))
+            {
+                *queue_index = position;
+            }
+        }
+
+        if mode != ShuffleMode::Off {
+            self.unshuffled_queue = Some(queue.clone());
+            let start = (*queue_index + 1).min(queue.len());
+            let mut upcoming = queue.split_off(start);
+            shuffle::shuffle(&mut upcoming, mode, &stats);
+            queue.extend(upcoming);
+        }
+    }
+
+    /// Changes the speed of the current track, and of later tracks without a saved speed
+    fn set_speed(&mut self, speed: f32) {
+        self.playback_state.settings.speed.pick(speed);
+    }
+
+    /// Remembers the current speed for the playing track, or for its directory
+    fn save_speed(&mut self, scope: SpeedScope) {
+        let Some(track) = self.now_playing() else {
+            return;
+        };
+        let path = match scope {
+            SpeedScope::Track => track.path.as_path(),
+            SpeedScope::Directory => track.path.parent().unwrap_or(&track.path),
+        };
+
+        let speed = self.playback_state.settings.speed.get();
+        let mut saved_speeds = self.playback_state.settings.saved_speeds.lock().unwrap();
+        self.status = Some(match saved_speeds.set(path, speed) {
+            Ok(()) => format!("Saved {speed}x for {}", path.display()),
+            Err(e) => format!("Couldn't save playback speed: {e}"),
+        });
+    }
+
+    fn increment_volume(&mut self, percentage: usize) {
+        self.volume_percentage += percentage;
+        if self.volume_percentage > 100 {
+            self.volume_percentage = 100;
+        }
+        self.playback_state
+            .sink
+            .set_volume(self.volume_percentage as f32 / 100.0);
+    }
+
+    fn decrement_volume(&mut self, percentage: usize) {
+        self.volume_percentage = self.volume_percentage.saturating_sub(percentage);
+        self.playback_state
+            .sink
+            .set_volume(self.volume_percentage as f32 / 100.0);
+    }
+
+    /// Gets the currently playing [`Track`]
+    fn now_playing(&self) -> Option<Track> {
+        let queue_guard = self.playback_state.queue.lock().unwrap();
+        queue_guard
+            .get(*self.playback_state.queue_index.lock().unwrap())
+            .cloned()
+    }
+
+    fn select_library_row(&mut self, row: usize) {
+        self.library_table_state.select(Some(row));
+        self.library_scrollbar_state = self.library_scrollbar_state.position(row);
+
+        self.request_image_redraw();
+    }
+
+    fn select_search_results_row(&mut self, row: usize) {
+        self.search_results_table_state.select(Some(row));
+        self.search_results_scrollbar_state = self.search_results_scrollbar_state.position(row);
+
+        self.request_image_redraw();
+    }
 
     fn select_sidebar_row(&mut self, row: usize) {
         self.sidebar_table_state.select(Some(row));
@@ -423,51 +2036,181 @@ impl Model<'_> {
     /// Adds a [`Track`] to the queue. Does not add it to the [`Sink`]
     fn queue_track(&mut self, track: Track) {
         let mut queue = self.playback_state.queue.lock().unwrap();
+        if let Some(unshuffled) = &mut self.unshuffled_queue {
+            unshuffled.push(track.clone());
+        }
         queue.push(track);
         self.sidebar_scrollbar_state = self.sidebar_scrollbar_state.content_length(queue.len());
     }
 
     /// Adds a [`Track`] to the [`Sink`] for playback
-    fn play_track(track: &Track, playback_state: &PlaybackState) {
-        let file = fs::File::open(&track.path)
-            .expect("Path should be valid, since we imported these files at startup");
-
-        // Add song to queue. TODO: display error message when attempting to open an unsupported file
-        if let Ok(decoder) = rodio::Decoder::try_from(file) {
-            *playback_state.insertion_offset.lock().unwrap() = 0;
-
-            let playback_clone = playback_state.clone();
-            let on_track_end = move || {
-                let mut queue_index = playback_clone.queue_index.lock().unwrap();
-                let queue = playback_clone.queue.lock().unwrap();
-                match *playback_clone.settings.repeat_mode.lock().unwrap() {
-                    RepeatMode::Off => {
-                        *queue_index += 1;
-                    }
-                    RepeatMode::Queue => {
-                        *queue_index += 1;
-                        if *queue_index >= queue.len() {
-                            *queue_index = 0;
-                        }
-                    }
-                    RepeatMode::Single => {
-                        // Do nothing because we want to play the same track
-                    }
+    /// Starts playing a [`Track`], returning whether it could be opened
+    ///
+    /// Tracks that can't be opened are added to [`PlaybackState::errors`].
+    fn play_track(track: &Track, playback_state: &PlaybackState) -> bool {
+        match Self::load_track(track, playback_state, Arc::default(), Handover::default()) {
+            Ok(source) => {
+                *playback_state.insertion_offset.lock().unwrap() = 0;
+                playback_state.sink.append(source);
+                playback_state.sink.play();
+                true
+            }
+            Err(e) => {
+                let message = format!("Couldn't play {}: {e}", track.path.display());
+                playback_state.errors.lock().unwrap().push(message);
+                false
+            }
+        }
+    }
+
+    /// Plays the track at `queue_index`, moving on through the queue past tracks that can't be
+    /// opened, e.g., because they were moved or deleted since the library was scanned
+    fn play_from(queue_index: &mut usize, queue: &[Track], playback_state: &PlaybackState) {
+        // Each track is tried at most once, in case none of them can be played
+        for _ in 0..queue.len() {
+            let Some(track) = queue.get(*queue_index) else {
+                return;
+            };
+            if Self::play_track(track, playback_state) {
+                return;
+            }
+
+            match playback_state.next_index(*queue_index, queue.len()) {
+                Some(next) if next != *queue_index => *queue_index = next,
+                Some(_) => return,
+                None => {
+                    *queue_index = queue.len();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Plays the track at the current queue index
+    fn play_current(&self) {
+        let mut queue_index = self.playback_state.queue_index.lock().unwrap();
+        let queue = self.playback_state.queue.lock().unwrap();
+        Self::play_from(&mut queue_index, &queue, &self.playback_state);
+    }
+
+    /// Opens a [`Track`] for playback, moving on through the queue when it ends
+    fn load_track(
+        track: &Track,
+        playback_state: &PlaybackState,
+        cancelled: Arc<AtomicBool>,
+        handover: Handover,
+    ) -> Result<impl Source + Send + 'static> {
+        let file = fs::File::open(&track.path)?;
+        let decoder = rodio::Decoder::try_from(file)?;
+
+        let playback_clone = playback_state.clone();
+        let track_clone = track.clone();
+        let source = Crossfade::new(
+            decoder,
+            track.duration,
+            playback_state.settings.crossfade_duration,
+            playback_state.settings.crossfade.clone(),
+            handover,
+            move || playback_clone.crossfade_handover(&track_clone),
+        );
+        let saved_speed = playback_state
+            .settings
+            .saved_speeds
+            .lock()
+            .unwrap()
+            .get(&track.path);
+        let source = VariableSpeed::new(
+            source,
+            playback_state.settings.speed.clone(),
+            saved_speed,
+            playback_state.position.clone(),
+        );
+
+        let playback_clone = playback_state.clone();
+        let on_track_end = move || {
+            let mut queue_index = playback_clone.queue_index.lock().unwrap();
+            let queue = playback_clone.queue.lock().unwrap();
+            let next_index = playback_clone.next_index(*queue_index, queue.len());
+
+            match playback_clone.preload.lock().unwrap().take() {
+                // The next track is already in the sink and starts right away
+                Some(preload) if preload.loaded && preload.is_next(next_index, &queue) => {
+                    *queue_index = preload.index;
+                    *playback_clone.insertion_offset.lock().unwrap() = 0;
                 }
-                if let Some(track) = queue.get(*queue_index) {
-                    Self::play_track(track, &playback_clone);
+                // The queue changed since the next track was loaded, so load the right one now
+                preload => {
+                    if let Some(preload) = preload {
+                        preload.cancelled.store(true, Ordering::Relaxed);
+                    }
+                    *queue_index = next_index.unwrap_or(queue.len());
+                    Self::play_from(&mut queue_index, &queue, &playback_clone);
                 }
-            };
+            }
+        };
+
+        Ok(WrappedSource::new(source, cancelled, on_track_end))
+    }
 
-            let source = WrappedSource::new(decoder, on_track_end);
-            playback_state.sink.append(source);
-            playback_state.sink.play();
+    /// Adds the track that comes next to the [`Sink`] ahead of time, for gapless playback
+    ///
+    /// Replaces the preloaded track if the queue or repeat mode changed since it was loaded.
+    fn preload_next(playback_state: &PlaybackState) {
+        if playback_state.sink.empty() {
+            return;
+        }
+
+        let next = {
+            let queue_index = playback_state.queue_index.lock().unwrap();
+            let queue = playback_state.queue.lock().unwrap();
+            let next_index = playback_state.next_index(*queue_index, queue.len());
+            let preload = playback_state.preload.lock().unwrap();
+            match &*preload {
+                Some(preload) if preload.is_next(next_index, &queue) => return,
+                None if next_index.is_none() => return,
+                _ => next_index.map(|index| (index, queue[index].clone())),
+            }
+        };
+
+        // Opening the file can be slow, so it's done without holding the locks that the end of
+        // the current track needs
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let handover = Handover::default();
+        let source = next.as_ref().and_then(|(_, track)| {
+            Self::load_track(track, playback_state, cancelled.clone(), handover.clone()).ok()
+        });
+
+        let queue_index = playback_state.queue_index.lock().unwrap();
+        let queue = playback_state.queue.lock().unwrap();
+        let next_index = playback_state.next_index(*queue_index, queue.len());
+        // The current track may have ended in the meantime, in which case the next tick retries
+        if next_index != next.as_ref().map(|(index, _)| *index) {
+            return;
+        }
+
+        let mut preload = playback_state.preload.lock().unwrap();
+        if let Some(preload) = preload.take() {
+            preload.cancelled.store(true, Ordering::Relaxed);
+        }
+        // Files that can't be played are remembered too, so they aren't opened on every tick
+        if let Some((index, track)) = next {
+            let loaded = source.is_some();
+            if let Some(source) = source {
+                playback_state.sink.append(source);
+            }
+            *preload = Some(Preload {
+                index,
+                path: track.path,
+                loaded,
+                cancelled,
+                handover,
+            });
         }
     }
 
     /// Skips to the next [`Track`] in the queue. If on the last track, stops playback.
     fn next_track(&mut self) {
-        self.playback_state.sink.stop();
+        self.playback_state.stop();
         let mut queue_index = self.playback_state.queue_index.lock().unwrap();
         let queue = self.playback_state.queue.lock().unwrap();
         match *self.playback_state.settings.repeat_mode.lock().unwrap() {
@@ -488,14 +2231,12 @@ impl Model<'_> {
             *queue_index = queue.len();
         }
 
-        if let Some(track) = queue.get(*queue_index) {
-            Self::play_track(track, &self.playback_state);
-        }
+        Self::play_from(&mut queue_index, &queue, &self.playback_state);
     }
 
     /// Plays the previous [`Track`] in the queue. If currently on the first track, restarts playback.
     fn previous_track(&mut self) {
-        self.playback_state.sink.stop();
+        self.playback_state.stop();
 
         let mut queue_index = self.playback_state.queue_index.lock().unwrap();
         if *queue_index > 0 {
@@ -503,8 +2244,54 @@ impl Model<'_> {
         }
 
         let queue = self.playback_state.queue.lock().unwrap();
-        if let Some(track) = queue.get(*queue_index) {
-            Self::play_track(track, &self.playback_state);
+        Self::play_from(&mut queue_index, &queue, &self.playback_state);
+    }
+
+    /// Moves the playback position within the current track
+    ///
+    /// Positions past the end are clamped to it, which finishes the track. Decoders that can't
+    /// seek leave playback as it was and show a status message instead.
+    fn seek(&mut self, position: Duration) {
+        let Some(track) = self.now_playing() else {
+            return;
+        };
+        let sink = &self.playback_state.sink;
+        if sink.empty() {
+            return;
+        }
+
+        let position = if track.duration.is_zero() {
+            position
+        } else {
+            position.min(track.duration)
+        };
+        // The sink waits for the track to seek, which updates `PlaybackState::position` as it
+        // does, so the progress bar shows the new position on the next draw. A failed seek
+        // leaves the track where it was, along with its position.
+        if let Err(e) = sink.try_seek(position) {
+            let format = track.codec.as_deref().unwrap_or("this format");
+            self.status = Some(format!("Can't seek in {format}: {e}"));
+        }
+    }
+
+    /// Plays the current track again from the start, even if it already finished
+    ///
+    /// Once the whole queue has finished, that's the last track in it.
+    fn restart_track(&mut self) {
+        if !self.playback_state.sink.empty() {
+            self.seek(Duration::ZERO);
+            return;
+        }
+
+        let track = {
+            let mut queue_index = self.playback_state.queue_index.lock().unwrap();
+            let queue = self.playback_state.queue.lock().unwrap();
+            // The index moves past the end of the queue when its last track finishes
+            *queue_index = (*queue_index).min(queue.len().saturating_sub(1));
+            queue.get(*queue_index).cloned()
+        };
+        if let Some(track) = track {
+            Self::play_track(&track, &self.playback_state);
         }
     }
 
@@ -515,13 +2302,18 @@ impl Model<'_> {
             self.next_track();
         }
 
-        let mut queue = self.playback_state.queue.lock().unwrap();
         let mut queue_index = self.playback_state.queue_index.lock().unwrap();
+        let mut queue = self.playback_state.queue.lock().unwrap();
         if index < *queue_index {
             *queue_index -= 1;
         }
 
-        queue.remove(index);
+        let removed = queue.remove(index);
+        if let Some(unshuffled) = &mut self.unshuffled_queue
+            && let Some(position) = unshuffled.iter().position(|t| t.path == removed.path)
+        {
+            unshuffled.remove(position);
+        }
         if *queue_index > queue.len() {
             *queue_index = queue.len();
         }
@@ -531,19 +2323,108 @@ impl Model<'_> {
 /// The player app
 pub struct Player<'a> {
     args: Args,
-    config: Config,
     model: Model<'a>,
+    /// Set if the library should be kept up to date while running
+    watcher: Option<LibraryWatcher>,
+    scan: Option<BackgroundScan>,
 }
 
 impl Player<'_> {
     /// Create a new player instance
     pub async fn new(args: Args) -> Result<Self> {
+        let config = Self::load_config(&args)?;
+        let mut model = Model::from_config(&config)?;
+
+        // Running without watching is better than not running, e.g., when the system's limit on
+        // watched directories is reached
+        let mut warnings: Vec<_> = model.status.take().into_iter().collect();
+        warnings.extend(Self::missing_path_warnings(&config));
+        let (watcher, watcher_error) = if config.watch_library {
+            match LibraryWatcher::new(&config.library_roots) {
+                Ok(watcher) => (Some(watcher), None),
+                Err(e) => (
+                    None,
+                    Some(format!("Not watching the library for changes: {e}")),
+                ),
+            }
+        } else {
+            (None, None)
+        };
+
+        let mut player = Player {
+            args,
+            model,
+            watcher,
+            scan: None,
+        };
+
+        player.import_tracks(player.args.reset_cache);
+        warnings.extend(player.model.status.take());
+        warnings.extend(watcher_error);
+        if !warnings.is_empty() {
+            player.model.status = Some(warnings.join(". "));
+        }
+
+        Ok(player)
+    }
+
+    /// Runs the command given on the command line without starting the player, if there is one
+    ///
+    /// Returns whether a command was run.
+    pub fn run_command(args: &Args) -> Result<bool> {
+        let Some(template) = &args.tag_from_path else {
+            return Ok(false);
+        };
+
+        let config = Self::load_config(args)?;
+        for warning in Self::missing_path_warnings(&config) {
+            eprintln!("{warning}");
+        }
+        let template = PathTemplate::parse(template)?;
+        let options = ScanOptions::from_config(&config)?;
+
+        // Only files that changed since the library was saved are read again
+        let mut database = library::open_database(&config)?;
+        let mut library = library::load_library(database.as_ref(), &config.library_sort)
+            .wrap_err("Couldn't load the library to tag it")?
+            .tracks;
+        let (tracks, _) = library::scan_roots(&options, library.clone(), |_| {});
+        let updated = autotag::tag_tracks(tracks, &options, &template, args.dry_run);
+        if updated.is_empty() {
+            return Ok(true);
+        }
+
+        // Only tracks that are already in the library are updated, the next scan adds the rest
+        let updated_index: HashMap<&Path, usize> = updated
+            .iter()
+            .enumerate()
+            .map(|(i, track)| (track.path.as_path(), i))
+            .collect();
+        for cached in &mut library {
+            if let Some(&i) = updated_index.get(cached.path.as_path()) {
+                // Keep the root's label, since a directory given on the command line replaces
+                // the configured roots
+                *cached = Track {
+                    root: cached.root.take(),
+                    ..updated[i].clone()
+                };
+            }
+        }
+        library::save_library(database.as_mut(), &library)?;
+
+        Ok(true)
+    }
+
+    fn load_config(args: &Args) -> Result<Config> {
         paths::create_config_files()?;
 
-        let mut config = if let Ok(path) = crate::paths::config_file().ok_or(eyre!(""))
-            && let Ok(config) = Config::load_from_file(&path)
+        // A config file that exists but can't be loaded, e.g., because a path in it uses an unset
+        // environment variable, is reported rather than silently replaced with the defaults
+        let mut config = if let Some(path) = crate::paths::config_file()
+            && path.exists()
         {
-            config
+            Config::load_from_file(&path)
+                .wrap_err_with(|| format!("Couldn't load config file {}", path.display()))?
         } else {
             let library_root = if let Some(ref dir) = args.dir {
                 dir.to_owned()
@@ -554,78 +2435,90 @@ impl Player<'_> {
             };
 
             Config {
-                library_root,
+                library_roots: vec![LibraryRoot::new(library_root)],
                 ..Default::default()
             }
         };
 
         if let Some(ref dir) = args.dir {
-            config.library_root = dir.clone();
+            config.library_roots = vec![LibraryRoot::new(dir.clone())];
         }
 
-        let model = Model::from_config(&config)?;
-
-        let mut player = Player {
-            args,
-            config,
-            model,
-        };
-
-        player.import_tracks();
-        player.model.tracks.sort_by(|a, b| {
-            Track::compare_by_fields(
-                a,
-                b,
-                &[CachedField::Artist, CachedField::Album, CachedField::Title],
-            )
-        });
-
-        Ok(player)
+        Ok(config)
     }
 
-    /// Read all tracks from the given [`Path`] and import their metadata into the player
-    fn get_tracks_from_disk(path: &Path) -> Vec<Track> {
-        let files = WalkDir::new(path)
+    /// Describes each configured path that doesn't exist
+    ///
+    /// These are warnings rather than errors, since a missing library root may just be a drive
+    /// that isn't mounted.
+    fn missing_path_warnings(config: &Config) -> Vec<String> {
+        config
+            .missing_paths()
             .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|f| f.file_type().is_file());
-
-        files.flat_map(|f| Track::try_from(f.path())).collect()
+            .map(|(option, path)| format!("{} in {option} doesn't exist", path.display()))
+            .collect()
     }
 
-    /// Read library track data from cache, or from disk if cache isn't found.
-    fn import_tracks(&mut self) {
-        let mut path = dirs::cache_dir().expect("Missing cache dir?");
-        path.push("minim");
-        path.push("library.csv");
-
-        self.model.tracks = if !self.args.reset_cache
-            && let Ok(tracks) = crate::cache::read_cache(&path)
-        {
-            tracks
-        } else {
-            Self::get_tracks_from_disk(&self.config.library_root)
+    /// Loads the library from the cache, then starts bringing it up to date with the files on
+    /// disk in the background
+    ///
+    /// Unless `full_rescan` is set, unchanged files aren't read again.
+    fn import_tracks(&mut self, full_rescan: bool) {
+        let cache = match &self.args.import_csv {
+            Some(path) => Some(
+                crate::cache::read_cache(path)
+                    .wrap_err_with(|| format!("Couldn't import {}", path.display())),
+            ),
+            None if full_rescan => None,
+            None => Some(
+                self.model
+                    .load_library()
+                    .wrap_err("Couldn't load the library"),
+            ),
+        };
+        // The scan rebuilds whatever couldn't be read
+        let cached = match cache {
+            Some(Ok(cache)) => {
+                if cache.corrupted > 0 {
+                    self.model.status = Some(format!(
+                        "Rebuilding {} corrupted library cache entries",
+                        cache.corrupted
+                    ));
+                }
+                cache.tracks
+            }
+            Some(Err(e)) => {
+                self.model.status = Some(format!("{e:#}, rescanning it"));
+                Vec::new()
+            }
+            None => Vec::new(),
         };
 
-        crate::cache::write_cache(&path, &self.model.tracks).unwrap();
+        match &mut self.model.database {
+            // Tracks from a CSV file are shown while the scan runs, like the database's own
+            Some(database) => {
+                if self.args.import_csv.is_some() || database.is_empty().unwrap_or(false) {
+                    let _ = database.upsert_tracks(&cached);
+                }
+            }
+            None => self.model.tracks = cached.clone(),
+        }
+        self.model.refresh_library();
+        self.start_scan(cached);
+    }
 
-        self.model.library_scrollbar_state = self
-            .model
-            .library_scrollbar_state
-            .content_length(self.model.tracks.len());
-
-        for track in &self.model.tracks {
-            self.model
-                .search_state
-                .injector
-                .push(track.clone(), |track, utf32_strings| {
-                    for (index, column) in
-                        self.model.search_state.columns_to_search.iter().enumerate()
-                    {
-                        utf32_strings[index] = track.cached_field_string(column).into();
-                    }
-                });
+    /// Starts scanning the library in the background, reusing tracks from `cached` for files
+    /// that haven't changed
+    fn start_scan(&mut self, cached: Vec<Track>) {
+        if self.scan.is_some() {
+            return;
         }
+
+        self.scan = Some(BackgroundScan::start(
+            self.model.scan_options.clone(),
+            cached,
+        ));
+        self.model.scan_progress = Some(ScanProgress::default());
     }
 
     /// Start the player
@@ -641,7 +2534,7 @@ impl Player<'_> {
             }
 
             if last_tick.elapsed() >= tick_rate {
-                self.on_tick();
+                self.on_tick().await;
                 last_tick = Instant::now();
             }
 
@@ -651,54 +2544,85 @@ impl Player<'_> {
         }
     }
 
-    fn on_tick(&mut self) {
-        // Update search results
-        self.model.search_state.matcher.tick(10);
-        let items = self.model.search_state.matcher.snapshot().matched_items(..);
-        let tracks = items.map(|item| item.data);
-        self.model.search_state.results = tracks.cloned().collect();
+    async fn on_tick(&mut self) {
+        // Add tracks from the library scan
+        if let Some(scan) = &self.scan {
+            let events: Vec<_> = scan.events().collect();
+            let finished = events
+                .iter()
+                .any(|event| matches!(event, ScanEvent::Finished(..)));
+            if finished {
+                self.scan = None;
+            }
+            if !events.is_empty() {
+                self.model.update(Message::Scan(events)).await;
+            }
 
-        // Update track art display
-        if self.model.playback_state.settings.show_track_art
-            && self.model.needs_image_redraw
-            && Instant::now() - self.model.last_track_focus_update > Duration::from_millis(250)
-            && let Some(track) = match self.model.player_state {
-                PlayerState {
-                    focus: PanelFocus::Sidebar,
-                    ..
-                } => match self.model.sidebar_table_state.selected() {
-                    Some(index) => self
-                        .model
-                        .playback_state
-                        .queue
-                        .lock()
-                        .unwrap()
-                        .get(index)
-                        .cloned(),
-                    None => None,
-                },
-                PlayerState {
-                    main_panel_view: MainPanelView::Library,
-                    ..
-                } => match self.model.library_table_state.selected() {
-                    Some(index) => self.model.tracks.get(index).cloned(),
-                    None => None,
-                },
-                PlayerState {
-                    main_panel_view: MainPanelView::SearchResults,
-                    ..
-                } => match self.model.search_results_table_state.selected() {
-                    Some(index) => self.model.search_state.results.get(index).cloned(),
-                    None => panic!(),
-                },
-                _ => None,
+            // Export once the library is up to date with the files on disk
+            if finished
+                && let Some(path) = self.args.export_csv.take()
+                && let Err(e) = self
+                    .model
+                    .all_tracks()
+                    .and_then(|tracks| crate::cache::write_cache(&path, &tracks))
+            {
+                self.model.status = Some(format!(
+                    "Couldn't export the library to {}: {e}",
+                    path.display()
+                ));
             }
+        }
+
+        // Load the track after the one that just started
+        Model::preload_next(&self.model.playback_state);
+
+        // Report tracks that were skipped because they couldn't be opened
+        let errors = std::mem::take(&mut *self.model.playback_state.errors.lock().unwrap());
+        if let Some(error) = errors.last() {
+            self.model.status = Some(match errors.len() {
+                1 => error.clone(),
+                n => format!("Skipped {n} tracks that couldn't be played. {error}"),
+            });
+        }
+
+        // Keep track of play history
+        if let Some(track) = self.model.now_playing()
+            && self.model.last_played.as_ref() != Some(&track.path)
+        {
+            self.model.update(Message::TrackStarted(track.path)).await;
+        }
+
+        // Pick up changes to the library
+        if let Some(paths) = self
+            .watcher
+            .as_mut()
+            .and_then(LibraryWatcher::changed_paths)
+        {
+            self.model.update(Message::LibraryChanged(paths)).await;
+        }
+
+        // Update search results, which the database returns all at once instead
+        if self.model.database.is_none() {
+            self.model.search_state.matcher.tick(10);
+            self.model.search_state.update_results();
+        }
+        self.model.search_results_scrollbar_state = self
+            .model
+            .search_results_scrollbar_state
+            .content_length(self.model.search_state.results.len());
+
+        // Update track art display
+        if self.model.playback_state.settings.show_track_art
+            && self.model.needs_image_redraw
+            && Instant::now() - self.model.last_track_focus_update > Duration::from_millis(250)
+            && let Some(track) = self.model.focused_track()
         {
             self.model.needs_image_redraw = false;
             let image_state = self.model.image_state.clone();
             let picker = self.model.picker.clone();
+            let patterns = self.model.cover_art_patterns.clone();
             tokio::spawn(async move {
-                Self::update_track_art(&track, &picker, image_state).await;
+                Self::update_track_art(&track, &patterns, &picker, image_state).await;
             });
         }
     }
@@ -713,10 +2637,11 @@ impl Player<'_> {
 
     async fn update_track_art(
         track: &Track,
+        sidecar_patterns: &[Pattern],
         picker: &Picker,
         image_state: Arc<Mutex<Option<StatefulProtocol>>>,
     ) {
-        let image = match track.track_art_as_dynamic_image().await {
+        let image = match track.track_art_as_dynamic_image(sidecar_patterns).await {
             Ok(image) => image,
             Err(_) => Self::placeholder_image(),
         };
@@ -739,6 +2664,10 @@ impl Player<'_> {
     }
 
     async fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if self.model.status.is_some() {
+            self.model.update(Message::ClearStatus).await;
+        }
+
         match (
             &self.model.player_state,
             key_event.modifiers,
@@ -753,10 +2682,39 @@ impl Player<'_> {
                 _,
             ) => self.handle_search_input_event(key_event).await,
 
+            (_, _, _) if self.model.tag_editor.is_some() => {
+                self.handle_tag_editor_event(key_event).await;
+            }
+
+            (_, _, _) if self.model.auto_tagger.is_some() => {
+                self.handle_auto_tagger_event(key_event).await;
+            }
+
+            (_, _, _) if self.model.seek_prompt.is_some() => {
+                self.handle_seek_prompt_event(key_event).await;
+            }
+
+            (_, _, _) if self.model.playlist_picker.is_some() => {
+                self.handle_playlist_picker_event(key_event).await;
+            }
+
+            (_, _, _) if self.model.track_details.is_some() => {
+                self.handle_track_details_event(key_event).await;
+            }
+
             (_, _, _) if self.model.player_state.show_help => {
                 self.model.update(Message::ToggleHelp).await;
             }
 
+            (_, _, _) if self.model.player_state.show_sort_picker => {
+                self.handle_sort_picker_event(key_event).await;
+            }
+
+            (_, _, KeyCode::Char('R')) => {
+                // Without the cached tracks, every file is read again
+                self.start_scan(self.model.all_tracks().unwrap_or_default());
+            }
+
             (_, KeyModifiers::NONE, KeyCode::Char('q')) => {
                 self.model.update(Message::Quit).await;
             }
@@ -781,6 +2739,30 @@ impl Player<'_> {
             (_, KeyModifiers::NONE, KeyCode::Char('i')) => {
                 self.model.update(Message::ToggleTrackArt).await;
             }
+            (_, _, KeyCode::Char('I')) => {
+                if let Some(track) = self.model.focused_track() {
+                    self.model.update(Message::ShowTrackDetails(track)).await;
+                }
+            }
+            (_, _, KeyCode::Char('T')) => {
+                if let Some(track) = self.model.focused_track() {
+                    let tracks = self.model.batch_tracks(track);
+                    self.model.update(Message::OpenAutoTagger(tracks)).await;
+                }
+            }
+            (_, _, KeyCode::Char('+')) => {
+                if let Some(track) = self.model.focused_track() {
+                    self.model.update(Message::ChangeRating(track, 1)).await;
+                }
+            }
+            (_, _, KeyCode::Char('-')) => {
+                if let Some(track) = self.model.focused_track() {
+                    self.model.update(Message::ChangeRating(track, -1)).await;
+                }
+            }
+            (_, _, KeyCode::Char('P')) => {
+                self.model.update(Message::OpenPlaylists).await;
+            }
 
             // Playback controls
             (_, _, KeyCode::Media(MediaKeyCode::PlayPause))
@@ -795,34 +2777,420 @@ impl Player<'_> {
             | (_, KeyModifiers::NONE, KeyCode::Char('n')) => {
                 self.model.update(Message::NextTrack).await;
             }
-            (_, KeyModifiers::NONE, KeyCode::Char('r')) => {
-                self.model.update(Message::CycleRepeatMode).await;
+            (_, KeyModifiers::NONE, KeyCode::Char('r')) => {
+                self.model.update(Message::CycleRepeatMode).await;
+            }
+            (_, _, KeyCode::Char('S')) => {
+                self.model.update(Message::CycleShuffleMode).await;
+            }
+            (_, _, KeyCode::Char('.')) | (_, KeyModifiers::SHIFT, KeyCode::Right) => {
+                self.model.update(Message::SeekForward).await;
+            }
+            (_, _, KeyCode::Char(',')) | (_, KeyModifiers::SHIFT, KeyCode::Left) => {
+                self.model.update(Message::SeekBackward).await;
+            }
+            (_, KeyModifiers::NONE, KeyCode::Char('g')) => {
+                self.model.update(Message::OpenSeekPrompt).await;
+            }
+            (_, KeyModifiers::NONE, KeyCode::Char('0')) => {
+                self.model.update(Message::RestartTrack).await;
+            }
+            (_, KeyModifiers::NONE, KeyCode::Char('x')) => {
+                self.model.update(Message::ToggleCrossfade).await;
+            }
+            (_, _, KeyCode::Char(']')) => {
+                self.model.update(Message::SpeedUp).await;
+            }
+            (_, _, KeyCode::Char('[')) => {
+                self.model.update(Message::SpeedDown).await;
+            }
+            (_, _, KeyCode::Char('=')) => {
+                self.model.update(Message::ResetSpeed).await;
+            }
+            (_, KeyModifiers::NONE, KeyCode::Char('w')) => {
+                self.model
+                    .update(Message::SaveSpeed(SpeedScope::Track))
+                    .await;
+            }
+            (_, _, KeyCode::Char('W')) => {
+                self.model
+                    .update(Message::SaveSpeed(SpeedScope::Directory))
+                    .await;
+            }
+
+            (
+                PlayerState {
+                    focus: PanelFocus::Sidebar,
+                    ..
+                },
+                _,
+                _,
+            ) => self.handle_sidebar_event(key_event).await,
+            (
+                PlayerState {
+                    main_panel_view: MainPanelView::Library,
+                    ..
+                },
+                _,
+                _,
+            ) => self.handle_library_event(key_event).await,
+            (
+                PlayerState {
+                    main_panel_view: MainPanelView::SearchResults,
+                    ..
+                },
+                _,
+                _,
+            ) => self.handle_search_results_event(key_event).await,
+            (
+                PlayerState {
+                    main_panel_view: MainPanelView::Artists,
+                    ..
+                },
+                _,
+                _,
+            ) => self.handle_artists_event(key_event).await,
+            (
+                PlayerState {
+                    main_panel_view: MainPanelView::ArtistTracks,
+                    ..
+                },
+                _,
+                _,
+            ) => self.handle_artist_tracks_event(key_event).await,
+        }
+    }
+
+    /// Row below `selected`, wrapping around to the top
+    fn next_row(selected: Option<usize>, len: usize) -> usize {
+        match selected {
+            Some(i) if i + 1 < len => i + 1,
+            _ => 0,
+        }
+    }
+
+    /// Row above `selected`, wrapping around to the bottom
+    fn previous_row(selected: Option<usize>, len: usize) -> usize {
+        match selected {
+            Some(i) if i > 0 => i - 1,
+            _ => len.saturating_sub(1),
+        }
+    }
+
+    async fn handle_artists_event(&mut self, key_event: KeyEvent) {
+        let browser = &self.model.artist_browser;
+        let len = browser.artists.len();
+        let selected = browser.artist_list_state.selected();
+
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Esc) => {
+                self.model.update(Message::FocusLibrary).await;
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('l'))
+            | (KeyModifiers::CONTROL, KeyCode::Right) => {
+                self.model.update(Message::FocusSidebar).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('j')) | (KeyModifiers::NONE, KeyCode::Down) => {
+                let row = Self::next_row(selected, len);
+                self.model.update(Message::SelectArtistRow(row)).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('k')) | (KeyModifiers::NONE, KeyCode::Up) => {
+                let row = Self::previous_row(selected, len);
+                self.model.update(Message::SelectArtistRow(row)).await;
+            }
+            (_, KeyCode::Home) => {
+                self.model.update(Message::SelectArtistRow(0)).await;
+            }
+            (_, KeyCode::End) => {
+                self.model
+                    .update(Message::SelectArtistRow(len.saturating_sub(1)))
+                    .await;
+            }
+            (_, KeyCode::Enter) => {
+                if let Some(artist) = selected.and_then(|index| browser.artists.get(index)) {
+                    self.model
+                        .update(Message::ShowArtistTracks(artist.clone()))
+                        .await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_artist_tracks_event(&mut self, key_event: KeyEvent) {
+        let browser = &self.model.artist_browser;
+        let len = browser.tracks.len();
+        let selected = browser.table_state.selected();
+        let track = selected
+            .and_then(|index| browser.tracks.get(index))
+            .cloned();
+
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Esc) => {
+                self.model.update(Message::ShowArtists).await;
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('l'))
+            | (KeyModifiers::CONTROL, KeyCode::Right) => {
+                self.model.update(Message::FocusSidebar).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('j')) | (KeyModifiers::NONE, KeyCode::Down) => {
+                let row = Self::next_row(selected, len);
+                self.model.update(Message::SelectArtistTrackRow(row)).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('k')) | (KeyModifiers::NONE, KeyCode::Up) => {
+                let row = Self::previous_row(selected, len);
+                self.model.update(Message::SelectArtistTrackRow(row)).await;
+            }
+            (_, KeyCode::Home) => {
+                self.model.update(Message::SelectArtistTrackRow(0)).await;
+            }
+            (_, KeyCode::End) => {
+                self.model
+                    .update(Message::SelectArtistTrackRow(len.saturating_sub(1)))
+                    .await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('a')) => {
+                if let Some(track) = track {
+                    self.model.update(Message::QueueAlbum(track)).await;
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('v')) => {
+                if let Some(track) = track {
+                    self.model.update(Message::ToggleMark(track)).await;
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('e')) => {
+                if let Some(track) = track {
+                    let tracks = self.model.batch_tracks(track);
+                    self.model.update(Message::OpenTagEditor(tracks)).await;
+                }
+            }
+            (mods, KeyCode::Enter) => {
+                if let Some(track) = track {
+                    match mods {
+                        KeyModifiers::ALT => {
+                            self.model.update(Message::QueueTrackNext(track)).await;
+                        }
+
+                        _ => {
+                            self.model.update(Message::QueueTrack(track)).await;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_track_details_event(&mut self, key_event: KeyEvent) {
+        let Some(details) = &self.model.track_details else {
+            return;
+        };
+        let max_scroll = details.lines.len().saturating_sub(1) as u16;
+
+        match (key_event.modifiers, key_event.code) {
+            (KeyModifiers::NONE, KeyCode::Char('j')) | (KeyModifiers::NONE, KeyCode::Down) => {
+                let scroll = (details.scroll + 1).min(max_scroll);
+                self.model.update(Message::ScrollTrackDetails(scroll)).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('k')) | (KeyModifiers::NONE, KeyCode::Up) => {
+                let scroll = details.scroll.saturating_sub(1);
+                self.model.update(Message::ScrollTrackDetails(scroll)).await;
+            }
+            (_, KeyCode::Home) => {
+                self.model.update(Message::ScrollTrackDetails(0)).await;
+            }
+            (_, KeyCode::End) => {
+                self.model
+                    .update(Message::ScrollTrackDetails(max_scroll))
+                    .await;
+            }
+            _ => {
+                self.model.update(Message::CloseTrackDetails).await;
+            }
+        }
+    }
+
+    async fn handle_auto_tagger_event(&mut self, key_event: KeyEvent) {
+        let Some(tagger) = &mut self.model.auto_tagger else {
+            return;
+        };
+        let scroll = tagger.scroll;
+
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Esc) => {
+                self.model.update(Message::CloseAutoTagger).await;
+            }
+            (_, KeyCode::Enter) => {
+                self.model.update(Message::SaveAutoTags).await;
+            }
+            (_, KeyCode::Down) => {
+                self.model
+                    .update(Message::ScrollAutoTagger(scroll + 1))
+                    .await;
+            }
+            (_, KeyCode::Up) => {
+                self.model
+                    .update(Message::ScrollAutoTagger(scroll.saturating_sub(1)))
+                    .await;
+            }
+            _ => {
+                if tagger.input.input(key_event) {
+                    tagger.update_preview();
+                }
+            }
+        }
+    }
+
+    async fn handle_seek_prompt_event(&mut self, key_event: KeyEvent) {
+        let Some(prompt) = &mut self.model.seek_prompt else {
+            return;
+        };
+
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Esc) => {
+                self.model.update(Message::CloseSeekPrompt).await;
+            }
+            (_, KeyCode::Enter) => {
+                self.model.update(Message::SubmitSeekPrompt).await;
+            }
+            _ => {
+                if prompt.input.input(key_event) {
+                    prompt.error = None;
+                }
+            }
+        }
+    }
+
+    async fn handle_playlist_picker_event(&mut self, key_event: KeyEvent) {
+        let Some(picker) = &mut self.model.playlist_picker else {
+            return;
+        };
+
+        if let Some(input) = &mut picker.input {
+            match key_event.code {
+                KeyCode::Esc => picker.input = None,
+                KeyCode::Enter => {
+                    let name = input.lines().join("").trim().to_owned();
+                    if !name.is_empty() {
+                        self.model.update(Message::SavePlaylist(name)).await;
+                    }
+                }
+                _ => {
+                    input.input(key_event);
+                }
+            }
+            return;
+        }
+
+        let rows = picker.names.len();
+        let selected = picker.list_state.selected();
+        let name = selected.and_then(|row| picker.names.get(row)).cloned();
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Esc) | (KeyModifiers::NONE, KeyCode::Char('q')) => {
+                self.model.update(Message::ClosePlaylists).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('n')) => picker.start_naming(),
+            (KeyModifiers::NONE, KeyCode::Char('j')) | (KeyModifiers::NONE, KeyCode::Down)
+                if rows > 0 =>
+            {
+                let row = match selected {
+                    Some(i) if i < rows - 1 => i + 1,
+                    _ => 0,
+                };
+
+                self.model.update(Message::SelectPlaylistRow(row)).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('k')) | (KeyModifiers::NONE, KeyCode::Up)
+                if rows > 0 =>
+            {
+                let row = match selected {
+                    Some(i) if i > 0 => i - 1,
+                    _ => rows - 1,
+                };
+
+                self.model.update(Message::SelectPlaylistRow(row)).await;
+            }
+            (_, KeyCode::Enter) => {
+                if let Some(name) = name {
+                    self.model.update(Message::QueuePlaylist(name)).await;
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('d')) => {
+                if let Some(name) = name {
+                    self.model.update(Message::DeletePlaylist(name)).await;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_tag_editor_event(&mut self, key_event: KeyEvent) {
+        let Some(editor) = &mut self.model.tag_editor else {
+            return;
+        };
+        let fields = editor.fields.len();
+        let selected = editor.selected_field;
+
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Esc) => {
+                self.model.update(Message::CloseTagEditor).await;
+            }
+            (_, KeyCode::Enter) => {
+                self.model.update(Message::SaveTagEdits).await;
+            }
+            (_, KeyCode::Tab) | (_, KeyCode::Down) => {
+                self.model
+                    .update(Message::SelectTagEditorField((selected + 1) % fields))
+                    .await;
+            }
+            (_, KeyCode::BackTab) | (_, KeyCode::Up) => {
+                self.model
+                    .update(Message::SelectTagEditorField(
+                        (selected + fields - 1) % fields,
+                    ))
+                    .await;
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('d')) => editor.clear_selected_field(),
+            _ => editor.input(key_event),
+        }
+    }
+
+    async fn handle_sort_picker_event(&mut self, key_event: KeyEvent) {
+        // First row is album order, the rest are the library columns
+        let rows = self.model.library_columns.len() + 1;
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Esc) | (KeyModifiers::NONE, KeyCode::Char('s' | 'q')) => {
+                self.model.update(Message::ToggleSortPicker).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('j')) | (KeyModifiers::NONE, KeyCode::Down) => {
+                let row = match self.model.sort_picker_state.selected() {
+                    Some(i) if i < rows - 1 => i + 1,
+                    _ => 0,
+                };
+
+                self.model.update(Message::SelectSortPickerRow(row)).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('k')) | (KeyModifiers::NONE, KeyCode::Up) => {
+                let row = match self.model.sort_picker_state.selected() {
+                    Some(i) if i > 0 => i - 1,
+                    _ => rows - 1,
+                };
+
+                self.model.update(Message::SelectSortPickerRow(row)).await;
             }
+            (_, KeyCode::Enter) => {
+                let sort = match self.model.sort_picker_state.selected() {
+                    Some(0) | None => SortKey::album_order(),
+                    Some(i) => self
+                        .model
+                        .sort_keys_for_field(self.model.library_columns[i - 1]),
+                };
 
-            (
-                PlayerState {
-                    focus: PanelFocus::Sidebar,
-                    ..
-                },
-                _,
-                _,
-            ) => self.handle_sidebar_event(key_event).await,
-            (
-                PlayerState {
-                    main_panel_view: MainPanelView::Library,
-                    ..
-                },
-                _,
-                _,
-            ) => self.handle_library_event(key_event).await,
-            (
-                PlayerState {
-                    main_panel_view: MainPanelView::SearchResults,
-                    ..
-                },
-                _,
-                _,
-            ) => self.handle_search_results_event(key_event).await,
+                self.model.update(Message::SortLibrary(sort)).await;
+                self.model.update(Message::ToggleSortPicker).await;
+            }
+            _ => {}
         }
     }
 
@@ -894,11 +3262,44 @@ impl Player<'_> {
             (KeyModifiers::CONTROL, KeyCode::Char('s')) => {
                 self.model.update(Message::ShowSearchResults).await;
             }
+            (KeyModifiers::NONE, KeyCode::Char('s')) => {
+                self.model.update(Message::ToggleSortPicker).await;
+            }
+            (_, KeyCode::Char('A')) => {
+                self.model.update(Message::ShowArtists).await;
+            }
+            (_, KeyCode::Char('L')) => {
+                self.model.update(Message::CycleRootFilter).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('v')) => {
+                if let Some(track) = self
+                    .model
+                    .library_table_state
+                    .selected()
+                    .and_then(|index| self.model.library_track(index))
+                {
+                    self.model.update(Message::ToggleMark(track)).await;
+                }
+            }
+            (_, KeyCode::Esc) => {
+                self.model.update(Message::ClearMarks).await;
+            }
+            (KeyModifiers::NONE, KeyCode::Char('e')) => {
+                if let Some(track) = self
+                    .model
+                    .library_table_state
+                    .selected()
+                    .and_then(|index| self.model.library_track(index))
+                {
+                    let tracks = self.model.batch_tracks(track);
+                    self.model.update(Message::OpenTagEditor(tracks)).await;
+                }
+            }
             // Library navigation
             (KeyModifiers::NONE, KeyCode::Char('j')) | (KeyModifiers::NONE, KeyCode::Down) => {
                 let row = match self.model.library_table_state.selected() {
                     Some(i) => {
-                        if i >= self.model.tracks.len() - 1 {
+                        if i + 1 >= self.model.library_len() {
                             0
                         } else {
                             i + 1
@@ -913,7 +3314,8 @@ impl Player<'_> {
                 let row = match self.model.library_table_state.selected() {
                     Some(i) => {
                         if i == 0 {
-                            self.model.tracks.len() - 1
+                            // Stays on the first row when the library is empty
+                            self.model.library_len().saturating_sub(1)
                         } else {
                             i - 1
                         }
@@ -928,18 +3330,28 @@ impl Player<'_> {
             }
             (_, KeyCode::End) => {
                 self.model
-                    .update(Message::SelectLibraryRow(self.model.tracks.len() - 1))
+                    .update(Message::SelectLibraryRow(
+                        self.model.library_len().saturating_sub(1),
+                    ))
                     .await;
             }
+            (KeyModifiers::NONE, KeyCode::Char('a')) => {
+                if let Some(track) = self
+                    .model
+                    .library_table_state
+                    .selected()
+                    .and_then(|index| self.model.library_track(index))
+                {
+                    self.model.update(Message::QueueAlbum(track)).await;
+                }
+            }
             (mods, KeyCode::Enter) => {
-                if let Some(index) = self.model.library_table_state.selected() {
-                    let track = self
-                        .model
-                        .tracks
-                        .get(index)
-                        .expect("Should be valid index")
-                        .clone();
-
+                if let Some(track) = self
+                    .model
+                    .library_table_state
+                    .selected()
+                    .and_then(|index| self.model.library_track(index))
+                {
                     match mods {
                         KeyModifiers::ALT => {
                             self.model.update(Message::QueueTrackNext(track)).await;
@@ -966,28 +3378,14 @@ impl Player<'_> {
             _ => {
                 self.model.search_bar.input(key_event);
 
-                // Update matcher. Note that this is NOT compatible with the upstream `nucleo`
-                // library behavior, and instead relies on a fork that OR's matches together
-                // See https://github.com/helix-editor/nucleo/issues/23#issuecomment-2643833781
-                // and https://github.com/helix-editor/nucleo/pull/53 for details
-                for column in 0..self.model.search_state.columns_to_search.len() {
-                    self.model.search_state.matcher.pattern.reparse(
-                        column,
-                        self.model
-                            .search_bar
-                            .lines()
-                            .first()
-                            .expect("Can't be empty"),
-                        CaseMatching::Ignore,
-                        Normalization::Smart,
-                        false,
-                    );
-                }
-
-                // Update results
-                let items = self.model.search_state.matcher.snapshot().matched_items(..);
-                let tracks = items.map(|item| item.data);
-                self.model.search_state.results = tracks.cloned().collect();
+                let query = self
+                    .model
+                    .search_bar
+                    .lines()
+                    .first()
+                    .expect("Can't be empty")
+                    .clone();
+                self.model.search(&query);
 
                 self.model.search_results_scrollbar_state = self
                     .model
@@ -1010,7 +3408,7 @@ impl Player<'_> {
             (KeyModifiers::NONE, KeyCode::Char('j')) | (KeyModifiers::NONE, KeyCode::Down) => {
                 let row = match self.model.search_results_table_state.selected() {
                     Some(i) => {
-                        if i >= self.model.search_state.results.len() - 1 {
+                        if i + 1 >= self.model.search_state.results.len() {
                             0
                         } else {
                             i + 1
@@ -1025,7 +3423,7 @@ impl Player<'_> {
                 let row = match self.model.search_results_table_state.selected() {
                     Some(i) => {
                         if i == 0 {
-                            self.model.search_state.results.len() - 1
+                            self.model.search_state.results.len().saturating_sub(1)
                         } else {
                             i - 1
                         }
@@ -1042,13 +3440,44 @@ impl Player<'_> {
             (_, KeyCode::End) => {
                 self.model
                     .update(Message::SelectSearchResultRow(
-                        self.model.search_state.results.len() - 1,
+                        self.model.search_state.results.len().saturating_sub(1),
                     ))
                     .await;
             }
             (KeyModifiers::NONE, KeyCode::Char('/')) => {
                 self.model.update(Message::FocusSearchBar).await;
             }
+            (KeyModifiers::NONE, KeyCode::Char('v')) => {
+                if let Some(track) = self
+                    .model
+                    .search_results_table_state
+                    .selected()
+                    .and_then(|index| self.model.search_state.results.get(index))
+                {
+                    self.model.update(Message::ToggleMark(track.clone())).await;
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('e')) => {
+                if let Some(track) = self
+                    .model
+                    .search_results_table_state
+                    .selected()
+                    .and_then(|index| self.model.search_state.results.get(index))
+                {
+                    let tracks = self.model.batch_tracks(track.clone());
+                    self.model.update(Message::OpenTagEditor(tracks)).await;
+                }
+            }
+            (KeyModifiers::NONE, KeyCode::Char('a')) => {
+                if let Some(track) = self
+                    .model
+                    .search_results_table_state
+                    .selected()
+                    .and_then(|index| self.model.search_state.results.get(index))
+                {
+                    self.model.update(Message::QueueAlbum(track.clone())).await;
+                }
+            }
             (mods, KeyCode::Enter) => {
                 if let Some(index) = self.model.search_results_table_state.selected() {
                     let track = self
@@ -1084,15 +3513,366 @@ impl Player<'_> {
             &Layout::horizontal([Constraint::Percentage(80), Constraint::Min(15)]);
         let primary_tab = primary_tab_layout.split(panel_splits[0]);
 
-        Self::render_library(&mut self.model, frame, primary_tab[0]);
+        match self.model.player_state.main_panel_view {
+            MainPanelView::Artists => Self::render_artists(&mut self.model, frame, primary_tab[0]),
+            _ => Self::render_library(&mut self.model, frame, primary_tab[0]),
+        }
         Self::render_sidebar(&mut self.model, frame, primary_tab[1]);
         Self::render_status_bar(&self.model, frame, panel_splits[1]);
 
+        if let Some(progress) = &self.model.scan_progress {
+            Self::render_scan_progress(&self.model, progress, panel_splits[0], frame);
+        }
+
+        if self.model.player_state.show_sort_picker {
+            Self::render_sort_picker(&mut self.model, frame);
+        }
+
+        if self.model.tag_editor.is_some() {
+            Self::render_tag_editor(&mut self.model, frame);
+        }
+
+        if self.model.auto_tagger.is_some() {
+            Self::render_auto_tagger(&mut self.model, frame);
+        }
+
+        if self.model.seek_prompt.is_some() {
+            Self::render_seek_prompt(&mut self.model, frame);
+        }
+
+        if self.model.playlist_picker.is_some() {
+            Self::render_playlist_picker(&mut self.model, frame);
+        }
+
+        if let Some(details) = &self.model.track_details {
+            Self::render_track_details(&self.model, details, frame);
+        }
+
         if self.model.player_state.show_help {
             Self::render_help(&self.model, frame);
         }
     }
 
+    fn render_scan_progress(model: &Model, progress: &ScanProgress, area: Rect, frame: &mut Frame) {
+        let lines = vec![
+            Line::raw(format!("Files found: {}", progress.found)),
+            Line::raw(format!("Up to date:  {}", progress.unchanged)),
+            Line::raw(format!("Read:        {}", progress.parsed)),
+            Line::raw(format!("Failed:      {}", progress.failed)),
+        ];
+
+        // Keep out of the way in the bottom right, since the player is usable while scanning
+        let width = 30;
+        let height = lines.len() as u16 + 2;
+        let area = Rect {
+            x: area.x + area.width.saturating_sub(width + 1),
+            y: area.y + area.height.saturating_sub(height + 1),
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(model.theme.focused_panel_border)
+            .title("Scanning Library");
+        let widget = Paragraph::new(lines).block(block);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(widget, area);
+    }
+
+    fn render_sort_picker(model: &mut Model, frame: &mut Frame) {
+        let primary = model.library_sort.first();
+        let is_album_order = model.library_sort == SortKey::album_order();
+
+        let mut items = vec![Line::raw(if is_album_order {
+            "Album order *"
+        } else {
+            "Album order"
+        })];
+        items.extend(model.library_columns.iter().map(|column| match primary {
+            Some(key) if !is_album_order && key.field == *column => Line::raw(format!(
+                "{} {}",
+                column.name(),
+                Self::sort_direction_indicator(key.direction)
+            )),
+            _ => Line::raw(column.name()),
+        }));
+
+        let width = 30;
+        let height = items.len() as u16 + 2;
+        let area = frame.area();
+        let area = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(model.theme.focused_panel_border)
+            .title("Sort by");
+        let list = List::new(items).block(block).highlight_style(
+            Style::default()
+                .bg(model.theme.table_selected_row_bg_focused)
+                .fg(model.theme.table_selected_row_fg_focused),
+        );
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut model.sort_picker_state);
+    }
+
+    fn render_track_details(model: &Model, details: &TrackDetailsPopup, frame: &mut Frame) {
+        let area = frame.area();
+        let margin = 4;
+        let area = area.inner(Margin {
+            horizontal: margin * 2,
+            vertical: margin,
+        });
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(model.theme.focused_panel_border)
+            .title("Track Details")
+            .title_bottom(Line::from(" j/k: scroll, any other key: close ").centered());
+        let widget = Paragraph::new(details.lines.clone())
+            .block(block)
+            .scroll((details.scroll, 0));
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(widget, area);
+    }
+
+    fn render_tag_editor(model: &mut Model, frame: &mut Frame) {
+        let Some(editor) = &mut model.tag_editor else {
+            return;
+        };
+
+        let title = match editor.tracks.as_slice() {
+            [track] => format!(
+                "Edit Tags: {}",
+                track.cached_field_string(&CachedField::Title)
+            ),
+            tracks => format!("Edit Tags: {} tracks", tracks.len()),
+        };
+        let error_lines = editor
+            .error
+            .as_ref()
+            .map_or(0, |error| error.lines().count() as u16);
+
+        let width = 60;
+        let height = editor.fields.len() as u16 * 3 + error_lines + 2;
+        let area = frame.area();
+        let area = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(model.theme.focused_panel_border)
+            .title(title)
+            .title_bottom(
+                Line::from(" Enter: save, Esc: cancel, Tab: next field, Ctrl-D: clear ").centered(),
+            );
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+
+        let mut constraints = vec![Constraint::Length(3); editor.fields.len()];
+        constraints.push(Constraint::Min(error_lines));
+        let layout = Layout::vertical(constraints).split(inner);
+
+        for (index, TagEditorField { field, input, .. }) in editor.fields.iter_mut().enumerate() {
+            let mut block = Block::bordered().title(field.name());
+            if index == editor.selected_field {
+                block = block.border_style(model.theme.focused_panel_border);
+                input.set_cursor_style(Style::default().reversed());
+            } else {
+                // Hide the cursor in unfocused fields
+                input.set_cursor_style(input.cursor_line_style());
+            }
+            input.set_block(block);
+            frame.render_widget(&*input, layout[index]);
+        }
+
+        if let Some(error) = &editor.error {
+            let error = Paragraph::new(error.as_str()).red();
+            frame.render_widget(error, layout[editor.fields.len()]);
+        }
+    }
+
+    fn render_auto_tagger(model: &mut Model, frame: &mut Frame) {
+        let Some(tagger) = &mut model.auto_tagger else {
+            return;
+        };
+
+        let area = frame.area();
+        let margin = 4;
+        let area = area.inner(Margin {
+            horizontal: margin * 2,
+            vertical: margin,
+        });
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(model.theme.focused_panel_border)
+            .title(format!("Tag from Path: {} tracks", tagger.tracks.len()))
+            .title_bottom(
+                Line::from(" Enter: write tags, Esc: cancel, Up/Down: scroll ").centered(),
+            );
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+
+        let layout = Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).split(inner);
+
+        tagger.input.set_block(
+            Block::bordered()
+                .title("Template")
+                .border_style(model.theme.focused_panel_border),
+        );
+        tagger.input.set_cursor_style(Style::default().reversed());
+        frame.render_widget(&tagger.input, layout[0]);
+
+        // Nothing is written until Enter is pressed, so this doubles as a dry run
+        let preview = if let Some(error) = &tagger.error {
+            Paragraph::new(error.as_str()).red()
+        } else if tagger.preview.is_empty() {
+            Paragraph::new("No changes")
+        } else {
+            let mut lines =
+                vec![Line::from(format!("{} tracks will change:", tagger.preview.len())).bold()];
+            for (track, changes) in &tagger.preview {
+                lines.push(Line::raw(""));
+                lines.push(Line::from(track.path.display().to_string()).bold());
+                lines.extend(
+                    changes
+                        .iter()
+                        .map(|change| Line::raw(format!("    {change}"))),
+                );
+            }
+            Paragraph::new(lines)
+        };
+        frame.render_widget(preview.scroll((tagger.scroll, 0)), layout[1]);
+    }
+
+    fn render_seek_prompt(model: &mut Model, frame: &mut Frame) {
+        let duration = model
+            .now_playing()
+            .map_or(Duration::ZERO, |track| track.duration);
+        let Some(prompt) = &mut model.seek_prompt else {
+            return;
+        };
+
+        let error_lines = u16::from(prompt.error.is_some());
+
+        let width = 40;
+        let height = 3 + error_lines + 2;
+        let area = frame.area();
+        let area = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(model.theme.focused_panel_border)
+            .title(format!(
+                "Jump to Time (of {})",
+                Track::format_duration(duration)
+            ))
+            .title_bottom(Line::from(" Enter: jump, Esc: cancel ").centered());
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+
+        let layout =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(error_lines)]).split(inner);
+
+        prompt.input.set_block(
+            Block::bordered()
+                .title("Time")
+                .border_style(model.theme.focused_panel_border),
+        );
+        prompt.input.set_cursor_style(Style::default().reversed());
+        frame.render_widget(&prompt.input, layout[0]);
+
+        if let Some(error) = &prompt.error {
+            frame.render_widget(Paragraph::new(error.as_str()).red(), layout[1]);
+        }
+    }
+
+    fn render_playlist_picker(model: &mut Model, frame: &mut Frame) {
+        let Some(picker) = &mut model.playlist_picker else {
+            return;
+        };
+
+        let items = if picker.names.is_empty() {
+            vec![Line::raw("No saved playlists").italic()]
+        } else {
+            picker
+                .names
+                .iter()
+                .map(|name| Line::raw(name.clone()))
+                .collect()
+        };
+        let input_lines = if picker.input.is_some() { 3 } else { 0 };
+
+        let width = 56;
+        let height = items.len() as u16 + input_lines + 2;
+        let area = frame.area();
+        let area = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(model.theme.focused_panel_border)
+            .title("Playlists")
+            .title_bottom(
+                Line::from(" Enter: queue, n: save queue, d: delete, Esc: close ").centered(),
+            );
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+
+        let layout =
+            Layout::vertical([Constraint::Length(input_lines), Constraint::Min(0)]).split(inner);
+
+        if let Some(input) = &mut picker.input {
+            input.set_block(
+                Block::bordered()
+                    .title("Save Queue As")
+                    .border_style(model.theme.focused_panel_border),
+            );
+            input.set_cursor_style(Style::default().reversed());
+            frame.render_widget(&*input, layout[0]);
+        }
+
+        let list = List::new(items).highlight_style(
+            Style::default()
+                .bg(model.theme.table_selected_row_bg_focused)
+                .fg(model.theme.table_selected_row_fg_focused),
+        );
+        frame.render_stateful_widget(list, layout[1], &mut picker.list_state);
+    }
+
+    fn sort_direction_indicator(direction: SortDirection) -> &'static str {
+        match direction {
+            SortDirection::Ascending => "\u{25b2}",
+            SortDirection::Descending => "\u{25bc}",
+        }
+    }
+
     fn render_help(_model: &Model, frame: &mut Frame) {
         let area = frame.area();
         let margin = 4;
@@ -1108,10 +3888,34 @@ impl Player<'_> {
             ("Scroll Down", "j"),
             ("Add to Queue", "Enter"),
             ("Queue Next", "A-Enter"),
+            ("Queue Album", "a"),
             ("Play/Pause", "p"),
             ("Next Track", "n"),
             ("Previous Track", "b"),
+            ("Seek Forward", "."),
+            ("Seek Back", ","),
+            ("Jump to Time", "g"),
+            ("Restart Track", "0"),
+            ("Toggle Crossfade", "x"),
+            ("Cycle Shuffle Mode", "S"),
+            ("Faster", "]"),
+            ("Slower", "["),
+            ("Normal Speed", "="),
+            ("Save Speed for Track", "w"),
+            ("Save Speed for Directory", "W"),
             ("Search", "/"),
+            ("Browse Artists", "A"),
+            ("Next Library Root", "L"),
+            ("Sort Library", "s"),
+            ("Mark Track", "v"),
+            ("Clear Marks", "Esc"),
+            ("Edit Tags", "e"),
+            ("Tag from Path", "T"),
+            ("Track Details", "I"),
+            ("Rate Up (sqlite)", "+"),
+            ("Rate Down (sqlite)", "-"),
+            ("Playlists (sqlite)", "P"),
+            ("Rescan Library", "R"),
             ("Switch Focus Left", "C-h"),
             ("Switch Focus Right", "C-l"),
             ("Remove from Queue", "d"),
@@ -1150,7 +3954,9 @@ impl Player<'_> {
 
         Self::render_gauges(model, frame, layout[0]);
 
-        if cfg!(debug_assertions) {
+        if let Some(status) = &model.status {
+            frame.render_widget(Line::from(status.as_str()).centered(), layout[1]);
+        } else if cfg!(debug_assertions) {
             #[cfg(debug_assertions)]
             Self::render_debug_info(model, frame, layout[1]);
         } else {
@@ -1171,6 +3977,8 @@ impl Player<'_> {
             Constraint::Percentage(80),
             Constraint::Min(2),
             Constraint::Length(2),
+            Constraint::Length(3),
+            Constraint::Length(6),
             Constraint::Min(1),
             Constraint::Percentage(20),
             Constraint::Min(1),
@@ -1180,12 +3988,11 @@ impl Player<'_> {
         let track = model.now_playing();
         let (label, ratio) = match track {
             Some(track) => {
-                let time = model.playback_state.sink.get_pos();
-                let duration = track.duration;
-                let ratio = time.as_secs() as f64 / duration as f64;
+                let position = model.playback_state.position();
+                let ratio = track.progress(position);
 
-                let time = Track::format_duration(time.as_secs());
-                let duration = Track::format_duration(duration);
+                let time = Track::format_duration(position);
+                let duration = Track::format_duration(track.duration);
                 (format!("{time}/{duration}"), ratio)
             }
             None => ("0:00/0:00".to_string(), 0.0),
@@ -1212,28 +4019,59 @@ impl Player<'_> {
         };
         let repeat_mode_indicator = Line::raw(repeat_character);
 
+        let shuffle_indicator = Line::raw(match model.playback_state.settings.shuffle_mode {
+            ShuffleMode::Off => "",
+            ShuffleMode::Tracks => "\u{21c4}",
+            ShuffleMode::Albums => "\u{21c4}A",
+            ShuffleMode::Weighted => "\u{21c4}W",
+        });
+
+        let speed_indicator = Line::raw(format!("{}x", model.playback_state.settings.speed.get()));
+
         frame.render_widget(&spacer, gauge_layout[0]);
         frame.render_widget(&progress_bar, gauge_layout[1]);
         frame.render_widget(&spacer, gauge_layout[2]);
         frame.render_widget(&repeat_mode_indicator, gauge_layout[3]);
-        frame.render_widget(&spacer, gauge_layout[4]);
-        frame.render_widget(&volume_gauge, gauge_layout[5]);
+        frame.render_widget(&shuffle_indicator, gauge_layout[4]);
+        frame.render_widget(&speed_indicator, gauge_layout[5]);
         frame.render_widget(&spacer, gauge_layout[6]);
+        frame.render_widget(&volume_gauge, gauge_layout[7]);
+        frame.render_widget(&spacer, gauge_layout[8]);
     }
 
-    fn track_to_row(track: &'_ Track) -> Row<'_> {
-        Row::new(vec![
-            Text::from(track.cached_field_string(&CachedField::Title)),
-            Text::from(track.cached_field_string(&CachedField::Artist)),
-            Text::from(format!(
-                "{} ",
-                track.cached_field_string(&CachedField::Duration)
-            ))
-            .right_aligned(),
-        ])
+    /// Values inferred from the track's path rather than its tags are shown in italics
+    fn track_to_row<'a>(track: &'a Track, columns: &[CachedField]) -> Row<'a> {
+        Row::new(columns.iter().map(|column| match column {
+            CachedField::Duration => {
+                Text::from(format!("{} ", track.cached_field_string(column))).right_aligned()
+            }
+            _ if track.inferred.contains(column) => {
+                Text::from(track.cached_field_string(column)).italic()
+            }
+            _ => Text::from(track.cached_field_string(column)),
+        }))
+    }
+
+    fn column_width(column: &CachedField) -> Constraint {
+        match column {
+            CachedField::DiscNumber
+            | CachedField::DiscTotal
+            | CachedField::TrackNumber
+            | CachedField::TrackTotal => Constraint::Length(6),
+            CachedField::Year => Constraint::Length(4),
+            CachedField::Codec => Constraint::Length(8),
+            CachedField::Root => Constraint::Length(16),
+            CachedField::Bitrate | CachedField::SampleRate => Constraint::Length(11),
+            CachedField::BitDepth | CachedField::Channels => Constraint::Length(9),
+            CachedField::Duration => Constraint::Min(9),
+            _ => Constraint::Fill(1),
+        }
     }
 
     fn render_library(model: &mut Model, frame: &mut Frame, area: Rect) {
+        // Rows inside the border, below the header
+        model.load_library_page(area.height.saturating_sub(4).into());
+
         let selected_row_style = match model.player_state.focus {
             PanelFocus::MainPanel => Style::default()
                 .bg(model.theme.table_selected_row_bg_focused)
@@ -1243,44 +4081,102 @@ impl Player<'_> {
                 .fg(model.theme.table_selected_row_fg_unfocused),
         };
 
-        let header = ["Title", "Artist", "Duration"]
-            .into_iter()
-            .map(ratatui::widgets::Cell::from)
+        let columns = &model.library_columns;
+        let sorted_by = match model.player_state.main_panel_view {
+            // Search results are ordered by how well they match instead, and artists' tracks are
+            // shown in album order
+            MainPanelView::SearchInput
+            | MainPanelView::SearchResults
+            | MainPanelView::ArtistTracks => None,
+            _ if model.library_sort == SortKey::album_order() => None,
+            _ => model.library_sort.first(),
+        };
+        let header = columns
+            .iter()
+            .map(|column| match sorted_by {
+                Some(key) if key.field == *column => ratatui::widgets::Cell::from(format!(
+                    "{} {}",
+                    column.name(),
+                    Self::sort_direction_indicator(key.direction)
+                )),
+                _ => ratatui::widgets::Cell::from(column.name()),
+            })
             .collect::<Row>()
             .bottom_margin(1);
 
-        let (rows, table_state, scrollbar_state) = match model.player_state.main_panel_view {
-            MainPanelView::SearchInput | MainPanelView::SearchResults => (
-                model.search_state.results.iter().map(Self::track_to_row),
-                &mut model.search_results_table_state,
-                &mut model.search_results_scrollbar_state,
-            ),
-            _ => (
-                model.tracks.iter().map(Self::track_to_row),
-                &mut model.library_table_state,
-                &mut model.library_scrollbar_state,
-            ),
-        };
+        // Only part of the library may have been read, starting from `first_row`
+        let (tracks, first_row, table_state, scrollbar_state) =
+            match model.player_state.main_panel_view {
+                MainPanelView::SearchInput | MainPanelView::SearchResults => (
+                    model.search_state.results.as_slice(),
+                    0,
+                    &mut model.search_results_table_state,
+                    &mut model.search_results_scrollbar_state,
+                ),
+                MainPanelView::ArtistTracks => (
+                    model.artist_browser.tracks.as_slice(),
+                    0,
+                    &mut model.artist_browser.table_state,
+                    &mut model.artist_browser.scrollbar_state,
+                ),
+                _ => match &model.library_page {
+                    Some(page) => (
+                        page.tracks.as_slice(),
+                        page.start,
+                        &mut model.library_table_state,
+                        &mut model.library_scrollbar_state,
+                    ),
+                    None => (
+                        model.tracks.as_slice(),
+                        0,
+                        &mut model.library_table_state,
+                        &mut model.library_scrollbar_state,
+                    ),
+                },
+            };
+        let marked_tracks = &model.marked_tracks;
+        let marked_row_fg = model.theme.table_marked_row_fg;
+        let rows = tracks.iter().map(|track| {
+            let row = Self::track_to_row(track, columns);
+            if marked_tracks.contains(track) {
+                row.fg(marked_row_fg)
+            } else {
+                row
+            }
+        });
 
-        let widths = [
-            Constraint::Percentage(50),
-            Constraint::Percentage(50),
-            Constraint::Min(9),
-        ];
+        let widths = columns.iter().map(Self::column_width);
 
         let table = Table::new(rows, widths)
             .header(header)
             .row_highlight_style(selected_row_style);
         let mut block = Block::bordered();
+        if model.player_state.main_panel_view == MainPanelView::ArtistTracks {
+            block = block.title(model.artist_browser.artist.as_str());
+        } else if model.player_state.main_panel_view == MainPanelView::Library
+            && let Some(root) = &model.root_filter
+        {
+            block = block.title(root.as_str());
+        }
 
         if model.player_state.focus == PanelFocus::MainPanel
             && (model.player_state.main_panel_view == MainPanelView::Library
-                || model.player_state.main_panel_view == MainPanelView::SearchResults)
+                || model.player_state.main_panel_view == MainPanelView::SearchResults
+                || model.player_state.main_panel_view == MainPanelView::ArtistTracks)
         {
             block = block.border_style(model.theme.focused_panel_border);
         }
 
-        frame.render_stateful_widget(table.block(block), area, table_state);
+        let mut rows_state = TableState::default()
+            .with_offset(table_state.offset().saturating_sub(first_row))
+            .with_selected(
+                table_state
+                    .selected()
+                    .map(|row| row.saturating_sub(first_row)),
+            );
+        frame.render_stateful_widget(table.block(block), area, &mut rows_state);
+        *table_state.offset_mut() = rows_state.offset() + first_row;
+        table_state.select(rows_state.selected().map(|row| row + first_row));
 
         let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight);
         frame.render_stateful_widget(
@@ -1316,6 +4212,27 @@ impl Player<'_> {
         };
     }
 
+    fn render_artists(model: &mut Model, frame: &mut Frame, area: Rect) {
+        let selected_row_style = match model.player_state.focus {
+            PanelFocus::MainPanel => Style::default()
+                .bg(model.theme.table_selected_row_bg_focused)
+                .fg(model.theme.table_selected_row_fg_focused),
+            _ => Style::default()
+                .bg(model.theme.table_selected_row_bg_unfocused)
+                .fg(model.theme.table_selected_row_fg_unfocused),
+        };
+
+        let mut block = Block::bordered().title("Artists");
+        if model.player_state.focus == PanelFocus::MainPanel {
+            block = block.border_style(model.theme.focused_panel_border);
+        }
+
+        let list = List::new(model.artist_browser.artists.iter().map(String::as_str))
+            .block(block)
+            .highlight_style(selected_row_style);
+        frame.render_stateful_widget(list, area, &mut model.artist_browser.artist_list_state);
+    }
+
     fn render_sidebar(model: &mut Model, frame: &mut Frame, area: Rect) {
         if model.playback_state.settings.show_track_art {
             let layout =
@@ -1421,13 +4338,16 @@ impl Player<'_> {
 // https://stackoverflow.com/questions/77876116/how-to-i-detect-when-a-sink-moves-to-the-next-source
 struct WrappedSource<S, F> {
     source: S,
+    /// Ends the source early, without calling `on_track_end`
+    cancelled: Arc<AtomicBool>,
     on_track_end: F,
 }
 
 impl<S, F> WrappedSource<S, F> {
-    fn new(source: S, on_track_end: F) -> Self {
+    fn new(source: S, cancelled: Arc<AtomicBool>, on_track_end: F) -> Self {
         Self {
             source,
+            cancelled,
             on_track_end,
         }
     }
@@ -1441,6 +4361,10 @@ where
     type Item = S::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+
         match self.source.next() {
             Some(s) => Some(s),
             None => {
@@ -1475,4 +4399,107 @@ where
     fn current_span_len(&self) -> Option<usize> {
         self.source.current_span_len()
     }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.source.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playback_state(repeat_mode: RepeatMode) -> PlaybackState {
+        let (sink, _output) = Sink::new();
+        let state = PlaybackState::new(sink);
+        *state.settings.repeat_mode.lock().unwrap() = repeat_mode;
+        state
+    }
+
+    fn track(path: &str) -> Track {
+        Track {
+            path: PathBuf::from(path),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn batch_edits_can_clear_differing_values() {
+        let mut first = track("a.flac");
+        first.genre = Some("Jazz".to_owned());
+        let mut second = track("b.flac");
+        second.genre = Some("Rock".to_owned());
+        let mut editor = TagEditor::new(vec![first, second]);
+        editor.selected_field = TagEditor::FIELDS
+            .iter()
+            .position(|field| *field == CachedField::Genre)
+            .unwrap();
+        assert!(editor.edits().is_empty());
+
+        editor.clear_selected_field();
+        assert_eq!(editor.edits(), [(CachedField::Genre, String::new())]);
+
+        editor.input(KeyEvent::from(KeyCode::Char('x')));
+        editor.input(KeyEvent::from(KeyCode::Backspace));
+        assert!(editor.edits().is_empty());
+    }
+
+    #[test]
+    fn next_index_follows_repeat_mode() {
+        let off = playback_state(RepeatMode::Off);
+        assert_eq!(off.next_index(0, 3), Some(1));
+        assert_eq!(off.next_index(2, 3), None);
+
+        let queue = playback_state(RepeatMode::Queue);
+        assert_eq!(queue.next_index(2, 3), Some(0));
+        assert_eq!(queue.next_index(0, 0), None);
+
+        let single = playback_state(RepeatMode::Single);
+        assert_eq!(single.next_index(1, 3), Some(1));
+        assert_eq!(single.next_index(3, 3), None);
+    }
+
+    #[test]
+    fn missing_tracks_are_skipped_and_reported() {
+        let state = playback_state(RepeatMode::Off);
+        let dir = tempfile::tempdir().unwrap();
+        let queue: Vec<_> = ["a.flac", "b.flac"]
+            .into_iter()
+            .map(|name| track(dir.path().join(name).to_str().unwrap()))
+            .collect();
+
+        let mut queue_index = 0;
+        Model::play_from(&mut queue_index, &queue, &state);
+        assert_eq!(queue_index, 2);
+        assert!(state.sink.empty());
+        let errors = state.errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("a.flac"), "{}", errors[0]);
+
+        let repeating = playback_state(RepeatMode::Queue);
+        let mut queue_index = 1;
+        Model::play_from(&mut queue_index, &queue, &repeating);
+        assert_eq!(repeating.errors.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn preload_is_invalidated_by_queue_edits() {
+        let preload = Preload {
+            index: 1,
+            path: PathBuf::from("b.flac"),
+            loaded: true,
+            cancelled: Arc::default(),
+            handover: Handover::default(),
+        };
+        let mut queue = vec![track("a.flac"), track("b.flac"), track("c.flac")];
+        assert!(preload.is_next(Some(1), &queue));
+
+        // Queueing a track to play next moves it into the preloaded index
+        queue.insert(1, track("d.flac"));
+        assert!(!preload.is_next(Some(1), &queue));
+
+        queue.remove(1);
+        assert!(!preload.is_next(Some(0), &queue));
+        assert!(!preload.is_next(None, &queue));
+    }
 }
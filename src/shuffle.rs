@@ -0,0 +1,165 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use fastrand::Rng;
+
+use crate::{Track, database::PlayStats};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ShuffleMode {
+    #[default]
+    Off,
+    /// Every track in a random order
+    Tracks,
+    /// Albums in a random order, with each album's tracks kept in order
+    Albums,
+    /// Rarely played and highly rated tracks are more likely to come first
+    Weighted,
+}
+
+impl ShuffleMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Tracks,
+            Self::Tracks => Self::Albums,
+            Self::Albums => Self::Weighted,
+            Self::Weighted => Self::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Tracks => "tracks",
+            Self::Albums => "albums",
+            Self::Weighted => "weighted",
+        }
+    }
+}
+
+/// How likely a track is to come early in a weighted shuffle
+///
+/// Goes from 1 for a 0/10 rating to 2 for a 10/10 one, divided by one more than the play count.
+/// Unrated tracks count as 5/10.
+fn weight(stats: PlayStats) -> f64 {
+    let rating = f64::from(stats.rating.unwrap_or(5));
+    (1.0 + rating / 10.0) / (1.0 + f64::from(stats.plays))
+}
+
+/// Puts `tracks` in a random order
+///
+/// `stats` is only used for [`ShuffleMode::Weighted`], which treats missing tracks as unplayed
+/// and unrated.
+pub(crate) fn shuffle(
+    tracks: &mut Vec<Track>,
+    mode: ShuffleMode,
+    stats: &HashMap<PathBuf, PlayStats>,
+) {
+    shuffle_with(&mut Rng::new(), tracks, mode, stats);
+}
+
+/// [`shuffle`] with the given random number generator, so tests can seed it
+fn shuffle_with(
+    rng: &mut Rng,
+    tracks: &mut Vec<Track>,
+    mode: ShuffleMode,
+    stats: &HashMap<PathBuf, PlayStats>,
+) {
+    match mode {
+        ShuffleMode::Off => {}
+        ShuffleMode::Tracks => rng.shuffle(tracks),
+        ShuffleMode::Albums => {
+            let mut albums: Vec<Vec<Track>> = Vec::new();
+            for track in tracks.drain(..) {
+                match albums.iter_mut().find(|album| album[0].same_album(&track)) {
+                    Some(album) => album.push(track),
+                    None => albums.push(vec![track]),
+                }
+            }
+            rng.shuffle(&mut albums);
+            tracks.extend(albums.into_iter().flatten());
+        }
+        ShuffleMode::Weighted => {
+            // Weighted sampling without replacement, by sorting on `u^(1/weight)`
+            let mut keyed: Vec<_> = tracks
+                .drain(..)
+                .map(|track| {
+                    let stats = stats.get(&track.path).copied().unwrap_or_default();
+                    (rng.f64().powf(1.0 / weight(stats)), track)
+                })
+                .collect();
+            keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+            tracks.extend(keyed.into_iter().map(|(_, track)| track));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn track(album: &str, number: u32) -> Track {
+        Track {
+            path: PathBuf::from(format!("{album}/{number}.flac")),
+            album: Some(album.to_owned()),
+            track_number: Some(number),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shuffles_keep_every_track() {
+        let queue: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .flat_map(|album| (1..=4).map(move |number| track(album, number)))
+            .collect();
+
+        for mode in [
+            ShuffleMode::Tracks,
+            ShuffleMode::Albums,
+            ShuffleMode::Weighted,
+        ] {
+            let mut shuffled = queue.clone();
+            shuffle(&mut shuffled, mode, &HashMap::new());
+            let mut sorted = shuffled.clone();
+            sorted.sort_by(|a, b| a.path.cmp(&b.path));
+            assert_eq!(sorted, queue, "{mode:?}");
+
+            if mode == ShuffleMode::Albums {
+                for album in shuffled.chunks(4) {
+                    let numbers: Vec<_> = album.iter().map(|t| t.track_number).collect();
+                    assert_eq!(numbers, [Some(1), Some(2), Some(3), Some(4)]);
+                    assert!(album.iter().all(|t| t.same_album(&album[0])));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_shuffle_favors_unplayed_and_highly_rated_tracks() {
+        let favorite = PlayStats {
+            plays: 0,
+            rating: Some(10),
+        };
+        let overplayed = PlayStats {
+            plays: 50,
+            rating: Some(2),
+        };
+        assert!(weight(favorite) > weight(PlayStats::default()));
+        assert!(weight(PlayStats::default()) > weight(overplayed));
+
+        let queue = vec![track("a", 1), track("a", 2)];
+        let stats = HashMap::from([
+            (queue[0].path.clone(), overplayed),
+            (queue[1].path.clone(), favorite),
+        ]);
+        let mut rng = Rng::with_seed(7);
+        let favorite_first = (0..100)
+            .filter(|_| {
+                let mut shuffled = queue.clone();
+                shuffle_with(&mut rng, &mut shuffled, ShuffleMode::Weighted, &stats);
+                shuffled[0] == queue[1]
+            })
+            .count();
+        assert!(favorite_first > 80, "{favorite_first}");
+    }
+}
@@ -0,0 +1,521 @@
+use std::{
+    collections::BTreeMap,
+    f32::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use color_eyre::{Result, eyre::eyre};
+use rodio::{ChannelCount, Sample, SampleRate, Sink, Source, source::SeekError};
+
+pub(crate) const MIN_SPEED: f32 = 0.5;
+pub(crate) const MAX_SPEED: f32 = 3.0;
+pub(crate) const SPEED_STEP: f32 = 0.25;
+
+#[derive(Clone, Debug)]
+/// The playback speed, shared with the playing tracks
+pub(crate) struct SpeedControl {
+    /// Bits of an `f32`, since there's no atomic float
+    speed: Arc<AtomicU32>,
+    /// Bits of the speed for tracks without a saved speed, which is the last one picked
+    default: Arc<AtomicU32>,
+    /// Whether to time-stretch rather than resample, which would also change the pitch
+    pub preserve_pitch: bool,
+    /// The sink to resample when the pitch isn't preserved
+    sink: Weak<Sink>,
+}
+
+impl SpeedControl {
+    pub fn new(preserve_pitch: bool, sink: Weak<Sink>) -> Self {
+        Self {
+            speed: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            default: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            preserve_pitch,
+            sink,
+        }
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    /// Changes the speed of the current track
+    pub fn set(&self, speed: f32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+        if !self.preserve_pitch
+            && let Some(sink) = self.sink.upgrade()
+        {
+            sink.set_speed(speed);
+        }
+    }
+
+    /// Changes the speed of the current track, and of later tracks without a saved speed
+    pub fn pick(&self, speed: f32) {
+        self.set(speed);
+        self.default
+            .store(self.speed.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// Switches to the speed for a track that's starting
+    fn start_track(&self, saved: Option<f32>) {
+        self.set(saved.unwrap_or_else(|| f32::from_bits(self.default.load(Ordering::Relaxed))));
+    }
+}
+
+#[derive(Debug, Default)]
+/// Speeds to play particular tracks or directories at, e.g., 1.5x for audiobooks
+pub(crate) struct SavedSpeeds {
+    /// Where the speeds are saved, if anywhere
+    file: Option<PathBuf>,
+    /// A file with saved speeds that couldn't be read, which is left alone until it's fixed
+    unreadable: Option<PathBuf>,
+    speeds: BTreeMap<String, f32>,
+}
+
+impl SavedSpeeds {
+    /// No saved speeds, since `file` couldn't be read
+    ///
+    /// New speeds only last until the player is closed, rather than overwriting the speeds in
+    /// the file.
+    pub fn unreadable(file: PathBuf) -> Self {
+        Self {
+            unreadable: Some(file),
+            ..Default::default()
+        }
+    }
+
+    /// Reads the saved speeds, starting out empty if there's no file yet
+    pub fn load(file: PathBuf) -> Result<Self> {
+        let speeds = match fs::read_to_string(&file) {
+            Ok(contents) => toml::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            file: Some(file),
+            unreadable: None,
+            speeds,
+        })
+    }
+
+    /// The speed saved for the track, or else for the closest directory it's in
+    pub fn get(&self, track: &Path) -> Option<f32> {
+        track
+            .ancestors()
+            .find_map(|path| self.speeds.get(path.to_string_lossy().as_ref()))
+            .copied()
+    }
+
+    /// Saves the speed for a track or directory, writing it to the file
+    pub fn set(&mut self, path: &Path, speed: f32) -> Result<()> {
+        self.speeds
+            .insert(path.to_string_lossy().into_owned(), speed);
+
+        if let Some(file) = &self.unreadable {
+            return Err(eyre!(
+                "{} couldn't be read, so it's only used for now",
+                file.display()
+            ));
+        }
+        match &self.file {
+            Some(file) => Ok(fs::write(file, toml::to_string(&self.speeds)?)?),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Plays a track at the shared speed, and keeps track of how far through it playback is
+///
+/// The speed switches to the track's saved speed, if it has one, as soon as it starts. When the
+/// pitch is preserved the track is time-stretched here, otherwise the sink resamples it. The
+/// sink's own position counts the samples played, which doesn't match the position in the track
+/// once the speed changes, so `position` is kept in milliseconds of the track instead.
+pub(crate) struct VariableSpeed<S> {
+    source: S,
+    speed: SpeedControl,
+    /// The speed saved for this track, if there is one
+    saved_speed: Option<f32>,
+    started: bool,
+    position: Arc<AtomicU64>,
+    /// Samples read from `source` so far
+    consumed: u64,
+    stretch: Option<TimeStretch>,
+}
+
+impl<S: Source> VariableSpeed<S> {
+    pub fn new(
+        source: S,
+        speed: SpeedControl,
+        saved_speed: Option<f32>,
+        position: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            source,
+            speed,
+            saved_speed,
+            started: false,
+            position,
+            consumed: 0,
+            stretch: None,
+        }
+    }
+
+    fn samples_per_second(&self) -> f64 {
+        f64::from(self.source.sample_rate()) * f64::from(self.source.channels())
+    }
+
+    fn update_position(&self) {
+        let secs = self.consumed as f64 / self.samples_per_second();
+        self.position
+            .store((secs * 1000.0) as u64, Ordering::Relaxed);
+    }
+}
+
+impl<S: Source> Iterator for VariableSpeed<S> {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            self.speed.start_track(self.saved_speed);
+        }
+        let speed = self.speed.get();
+        let before = self.consumed;
+
+        let sample = if self.speed.preserve_pitch && speed != 1.0 {
+            let channels = self.source.channels();
+            let sample_rate = self.source.sample_rate();
+            let stretch = self
+                .stretch
+                .get_or_insert_with(|| TimeStretch::new(channels, sample_rate));
+
+            let source = &mut self.source;
+            let consumed = &mut self.consumed;
+            let mut input = std::iter::from_fn(|| {
+                let sample = source.next()?;
+                *consumed += 1;
+                Some(sample)
+            });
+            stretch.next(&mut input, speed)
+        } else {
+            // Whatever the stretcher had buffered is skipped, which is only a few milliseconds
+            self.stretch = None;
+            let sample = self.source.next();
+            self.consumed += u64::from(sample.is_some());
+            sample
+        };
+
+        // Often enough for the progress bar, without writing on every sample
+        if before == 0 || before / 2048 != self.consumed / 2048 {
+            self.update_position();
+        }
+
+        sample
+    }
+}
+
+impl<S: Source> Source for VariableSpeed<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        // Stretched samples don't line up with the source's spans
+        if self.stretch.is_some() {
+            None
+        } else {
+            self.source.current_span_len()
+        }
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+
+    /// Positions are in the track, regardless of the speed
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)?;
+        self.consumed = (pos.as_secs_f64() * self.samples_per_second()) as u64;
+        self.stretch = None;
+        self.update_position();
+        Ok(())
+    }
+}
+
+/// Changes the speed of audio without changing its pitch, using WSOLA
+///
+/// Overlapping windows of the input are added together, spaced further apart than they were read
+/// to slow down, or closer together to speed up. Each window is shifted slightly to where it best
+/// lines up with the one before it, which avoids the warbling of plain overlap-add.
+struct TimeStretch {
+    channels: usize,
+    /// Frames in each window
+    window_len: usize,
+    /// Frames between windows in the output, which is half a window
+    hop: usize,
+    /// How many frames each window can be shifted by to line up with the previous one
+    tolerance: usize,
+    window: Vec<f32>,
+    /// Interleaved samples read so far, minus those no window needs anymore
+    input: Vec<f32>,
+    /// Frame in `input` where the next window would be read without any shifting
+    position: f64,
+    /// Frame in `input` halfway through the previous window, which the next window should
+    /// continue from as seamlessly as possible
+    natural: Option<usize>,
+    /// Second half of the previous window, which the next one is added to
+    overlap: Vec<f32>,
+    output: Vec<f32>,
+    output_index: usize,
+    finished: bool,
+}
+
+impl TimeStretch {
+    fn new(channels: ChannelCount, sample_rate: SampleRate) -> Self {
+        let channels = usize::from(channels.max(1));
+        // 40ms windows work well for speech and most music
+        let hop = (sample_rate as usize / 50).max(1);
+        let window_len = hop * 2;
+        let window = (0..window_len)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / window_len as f32).cos())
+            .collect();
+
+        Self {
+            channels,
+            window_len,
+            hop,
+            tolerance: sample_rate as usize / 125,
+            window,
+            input: Vec::new(),
+            position: 0.0,
+            natural: None,
+            overlap: vec![0.0; hop * channels],
+            output: Vec::new(),
+            output_index: 0,
+            finished: false,
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    fn next(&mut self, input: &mut impl Iterator<Item = Sample>, speed: f32) -> Option<Sample> {
+        while self.output_index >= self.output.len() {
+            if self.finished {
+                return None;
+            }
+            self.output.clear();
+            self.output_index = 0;
+            self.add_window(input, speed);
+        }
+
+        let sample = self.output[self.output_index];
+        self.output_index += 1;
+        Some(sample)
+    }
+
+    /// Reads the next window and outputs the half of it that's done overlapping
+    fn add_window(&mut self, input: &mut impl Iterator<Item = Sample>, speed: f32) {
+        let target = self.position.round() as usize;
+        let needed = (target + self.tolerance + self.window_len) * self.channels;
+        self.input
+            .extend(input.take(needed.saturating_sub(self.input.len())));
+
+        if target + self.window_len > self.frames() {
+            // Let the last window fade out
+            self.output.append(&mut self.overlap);
+            self.finished = true;
+            return;
+        }
+
+        let start = match self.natural {
+            Some(natural) => self.best_start(natural, target),
+            None => target,
+        };
+
+        let channels = self.channels;
+        for frame in 0..self.window_len {
+            let gain = self.window[frame];
+            for channel in 0..channels {
+                let sample = self.input[(start + frame) * channels + channel] * gain;
+                let index = frame * channels + channel;
+                if frame < self.hop {
+                    self.output.push(self.overlap[index] + sample);
+                } else {
+                    self.overlap[index - self.hop * channels] = sample;
+                }
+            }
+        }
+
+        let natural = start + self.hop;
+        self.position += self.hop as f64 * f64::from(speed);
+
+        // Drop input that no later window can start in or be compared against
+        let earliest_target = (self.position.floor() as usize).saturating_sub(self.tolerance);
+        let keep_from = natural.min(earliest_target);
+        self.input.drain(..keep_from * channels);
+        self.position -= keep_from as f64;
+        self.natural = Some(natural - keep_from);
+    }
+
+    /// Where to start the window near `target` whose first half best matches the input from
+    /// `natural`
+    fn best_start(&self, natural: usize, target: usize) -> usize {
+        // Compare mono mixes of every fourth frame, which is plenty to line up the waveforms
+        const STRIDE: usize = 4;
+        let mono = |frame: usize| -> f32 {
+            self.input[frame * self.channels..(frame + 1) * self.channels]
+                .iter()
+                .sum()
+        };
+
+        let first = target.saturating_sub(self.tolerance);
+        let last = (target + self.tolerance).min(self.frames() - self.window_len);
+        let mut best = (f32::MIN, target);
+        for start in first..=last {
+            let mut correlation = 0.0;
+            let mut energy = f32::EPSILON;
+            for offset in (0..self.hop).step_by(STRIDE) {
+                let candidate = mono(start + offset);
+                correlation += candidate * mono(natural + offset);
+                energy += candidate * candidate;
+            }
+            let score = correlation / energy.sqrt();
+            if score > best.0 {
+                best = (score, start);
+            }
+        }
+
+        best.1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    const SAMPLE_RATE: SampleRate = 8000;
+
+    /// A second of a 200 Hz sine wave
+    fn sine() -> Vec<f32> {
+        (0..SAMPLE_RATE)
+            .map(|n| (2.0 * PI * 200.0 * n as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    /// Upward zero crossings per second, which is the frequency of a sine wave
+    fn frequency(samples: &[f32]) -> f32 {
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 * SAMPLE_RATE as f32 / samples.len() as f32
+    }
+
+    #[test]
+    fn time_stretch_keeps_the_pitch() {
+        for speed in [0.5, 1.5, 3.0] {
+            let mut stretch = TimeStretch::new(1, SAMPLE_RATE);
+            let mut input = sine().into_iter();
+            let output: Vec<_> = std::iter::from_fn(|| stretch.next(&mut input, speed)).collect();
+
+            let expected = SAMPLE_RATE as f32 / speed;
+            let length = output.len() as f32;
+            assert!(
+                (length - expected).abs() < 0.1 * SAMPLE_RATE as f32,
+                "{speed}: {length}"
+            );
+            // Skip the first and last windows, which fade in and out
+            let middle = &output[stretch.window_len..output.len() - stretch.window_len];
+            assert!((frequency(middle) - 200.0).abs() < 10.0, "{speed}");
+        }
+    }
+
+    #[test]
+    fn position_is_in_track_time() {
+        let speed = SpeedControl::new(true, Weak::new());
+        let position = Arc::new(AtomicU64::new(0));
+        let source = SamplesBuffer::new(1, SAMPLE_RATE, sine());
+        let mut playing = VariableSpeed::new(source, speed.clone(), Some(2.0), position.clone());
+
+        // The saved speed applies once the track starts
+        assert_eq!(speed.get(), 1.0);
+        playing
+            .by_ref()
+            .take(SAMPLE_RATE as usize / 4)
+            .for_each(drop);
+        // A quarter of a second in, half a second of the track has been played
+        let millis = position.load(Ordering::Relaxed);
+        assert!((450..=600).contains(&millis), "{millis}");
+
+        playing.try_seek(Duration::from_millis(100)).unwrap();
+        assert_eq!(position.load(Ordering::Relaxed), 100);
+
+        speed.set(10.0);
+        assert_eq!(speed.get(), MAX_SPEED);
+
+        // Tracks without a saved speed go back to the last speed that was picked
+        speed.pick(1.5);
+        speed.set(2.0);
+        let source = SamplesBuffer::new(1, SAMPLE_RATE, sine());
+        VariableSpeed::new(source, speed.clone(), None, position)
+            .next()
+            .unwrap();
+        assert_eq!(speed.get(), 1.5);
+    }
+
+    #[test]
+    fn failed_seeks_keep_the_position() {
+        let speed = SpeedControl::new(true, Weak::new());
+        let position = Arc::new(AtomicU64::new(0));
+        // Buffered sources can't seek
+        let source = SamplesBuffer::new(1, SAMPLE_RATE, sine()).buffered();
+        let mut playing = VariableSpeed::new(source, speed, None, position.clone());
+        playing
+            .by_ref()
+            .take(SAMPLE_RATE as usize / 2)
+            .for_each(drop);
+        let before = position.load(Ordering::Relaxed);
+
+        assert!(playing.try_seek(Duration::from_millis(100)).is_err());
+        assert_eq!(position.load(Ordering::Relaxed), before);
+    }
+
+    #[test]
+    fn saved_speeds_apply_to_directories() {
+        let mut saved = SavedSpeeds::default();
+        saved.set(Path::new("/books"), 1.5).unwrap();
+        saved.set(Path::new("/books/music/song.mp3"), 1.0).unwrap();
+
+        assert_eq!(saved.get(Path::new("/books/author/01.mp3")), Some(1.5));
+        assert_eq!(saved.get(Path::new("/books/music/song.mp3")), Some(1.0));
+        assert_eq!(saved.get(Path::new("/music/song.mp3")), None);
+    }
+
+    #[test]
+    fn unreadable_files_are_left_alone() {
+        let temp = tempfile::tempdir().unwrap();
+        let file = temp.path().join("speeds.toml");
+        fs::write(&file, "not = [toml").unwrap();
+
+        assert!(SavedSpeeds::load(file.clone()).is_err());
+        let mut saved = SavedSpeeds::unreadable(file.clone());
+        assert!(saved.set(Path::new("/books"), 1.5).is_err());
+        assert_eq!(saved.get(Path::new("/books/01.mp3")), Some(1.5));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "not = [toml");
+    }
+}
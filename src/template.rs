@@ -0,0 +1,189 @@
+use std::path::{Component, Path};
+
+use color_eyre::{Result, eyre::eyre};
+
+use crate::track::CachedField;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Field(CachedField),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A pattern for a track's path within its library root, e.g.,
+/// `{artist}/{album}/{track} - {title}`
+///
+/// Templates are matched against the end of the path, without the file extension, so the
+/// example also matches files nested more deeply. The placeholders are `{title}`, `{artist}`,
+/// `{album}`, `{albumartist}`, `{disc}`, `{track}`, `{year}` and `{genre}`.
+pub(crate) struct PathTemplate {
+    template: String,
+    /// Tokens for each path component
+    components: Vec<Vec<Token>>,
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let components = template
+            .split('/')
+            .map(|component| Self::parse_component(template, component))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            template: template.to_owned(),
+            components,
+        })
+    }
+
+    /// The template as written
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    fn parse_component(template: &str, mut component: &str) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        while !component.is_empty() {
+            let Some(rest) = component.strip_prefix('{') else {
+                let end = component.find('{').unwrap_or(component.len());
+                tokens.push(Token::Literal(component[..end].to_owned()));
+                component = &component[end..];
+                continue;
+            };
+
+            let (name, rest) = rest
+                .split_once('}')
+                .ok_or_else(|| eyre!("Missing closing brace in path template {template}"))?;
+            let field = match name {
+                "title" => CachedField::Title,
+                "artist" => CachedField::Artist,
+                "album" => CachedField::Album,
+                "albumartist" => CachedField::AlbumArtist,
+                "disc" => CachedField::DiscNumber,
+                "track" => CachedField::TrackNumber,
+                "year" => CachedField::Year,
+                "genre" => CachedField::Genre,
+                _ => {
+                    return Err(eyre!(
+                        "Unknown field {{{name}}} in path template {template}"
+                    ));
+                }
+            };
+            if matches!(tokens.last(), Some(Token::Field(_))) {
+                return Err(eyre!(
+                    "Fields need text between them in path template {template}"
+                ));
+            }
+            tokens.push(Token::Field(field));
+            component = rest;
+        }
+
+        Ok(tokens)
+    }
+
+    /// The value of each field in `path`, or `None` if it doesn't match
+    pub fn captures(&self, path: &Path) -> Option<Vec<(CachedField, String)>> {
+        let path = path.with_extension("");
+        let components: Vec<_> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+        let start = components.len().checked_sub(self.components.len())?;
+
+        let mut captures = Vec::new();
+        for (tokens, component) in self.components.iter().zip(&components[start..]) {
+            if !Self::match_tokens(tokens, component, &mut captures) {
+                return None;
+            }
+        }
+
+        Some(captures)
+    }
+
+    /// Matches a path component, trying each place the text after a field could start
+    fn match_tokens(
+        tokens: &[Token],
+        text: &str,
+        captures: &mut Vec<(CachedField, String)>,
+    ) -> bool {
+        match tokens {
+            [] => text.is_empty(),
+            [Token::Literal(literal), rest @ ..] => text
+                .strip_prefix(literal.as_str())
+                .is_some_and(|text| Self::match_tokens(rest, text, captures)),
+            [Token::Field(field), rest @ ..] => {
+                let ends: Vec<usize> = match rest.first() {
+                    Some(Token::Literal(literal)) => text
+                        .match_indices(literal.as_str())
+                        .map(|(index, _)| index)
+                        .collect(),
+                    _ => vec![text.len()],
+                };
+
+                for end in ends {
+                    let value = text[..end].trim();
+                    if !Self::is_valid(field, value) {
+                        continue;
+                    }
+                    captures.push((*field, value.to_owned()));
+                    if Self::match_tokens(rest, &text[end..], captures) {
+                        return true;
+                    }
+                    captures.pop();
+                }
+
+                false
+            }
+        }
+    }
+
+    fn is_valid(field: &CachedField, value: &str) -> bool {
+        match field {
+            CachedField::DiscNumber | CachedField::TrackNumber | CachedField::Year => {
+                value.parse::<u32>().is_ok()
+            }
+            _ => !value.is_empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn captures_fields_from_the_end_of_the_path() {
+        let template = PathTemplate::parse("{artist}/{year} - {album}/{track} - {title}").unwrap();
+        let captures = template
+            .captures(Path::new(
+                "Rock/Artist/1999 - Album - Live/01 - Song - Remix.mp3",
+            ))
+            .unwrap();
+
+        assert_eq!(
+            captures,
+            [
+                (CachedField::Artist, "Artist".to_owned()),
+                (CachedField::Year, "1999".to_owned()),
+                (CachedField::Album, "Album - Live".to_owned()),
+                (CachedField::TrackNumber, "01".to_owned()),
+                (CachedField::Title, "Song - Remix".to_owned()),
+            ]
+        );
+        assert_eq!(template.captures(Path::new("Album/01 - Song.mp3")), None);
+        assert_eq!(
+            template.captures(Path::new("Artist/Album/01 - Song.mp3")),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(PathTemplate::parse("{artist}/{album").is_err());
+        assert!(PathTemplate::parse("{composer}/{title}").is_err());
+        assert!(PathTemplate::parse("{track}{title}").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{path::Path, str::FromStr};
 
-use color_eyre::eyre::{self, Result, eyre};
+use color_eyre::eyre::{self, Result};
 use ratatui::style::Color;
 use serde::{Deserialize, Serialize};
 
@@ -13,6 +13,8 @@ pub struct Theme {
     pub table_selected_row_fg_focused: Color,
     pub table_selected_row_bg_unfocused: Color,
     pub table_selected_row_fg_unfocused: Color,
+    #[serde(default = "Theme::default_table_marked_row_fg")]
+    pub table_marked_row_fg: Color,
     pub progress_bar_unfilled: Color,
     pub progress_bar_filled: Color,
     pub sidebar_now_playing_fg: Color,
@@ -20,14 +22,7 @@ pub struct Theme {
 }
 
 impl Theme {
-    pub fn get_theme_by_name(name: &str) -> Result<Self> {
-        let mut path = crate::paths::theme_dir().ok_or(eyre!("Couldn't find config dir"))?;
-        path.push(format!("{}.toml", name));
-
-        Self::load_from_file(path)
-    }
-
-    fn load_from_file<T>(path: T) -> Result<Self>
+    pub fn load_from_file<T>(path: T) -> Result<Self>
     where
         T: AsRef<Path>,
     {
@@ -35,6 +30,10 @@ impl Theme {
 
         Self::from_str(&s)
     }
+
+    fn default_table_marked_row_fg() -> Color {
+        Color::Yellow
+    }
 }
 
 impl FromStr for Theme {
@@ -54,6 +53,7 @@ impl Default for Theme {
             table_selected_row_fg_focused: Color::Black,
             table_selected_row_bg_unfocused: Color::Gray,
             table_selected_row_fg_unfocused: Color::Black,
+            table_marked_row_fg: Self::default_table_marked_row_fg(),
             progress_bar_unfilled: Color::White,
             progress_bar_filled: Color::Blue,
             sidebar_now_playing_fg: Color::Blue,
@@ -1,37 +1,105 @@
 use std::{
     borrow::Cow,
     cmp::Ordering,
+    fs::Metadata,
     io::Cursor,
     path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
 };
 
 use color_eyre::{Result, eyre::eyre};
-use image::{DynamicImage, ImageReader};
+use glob::{MatchOptions, Pattern};
+use image::{DynamicImage, ImageFormat, ImageReader};
 use lofty::{
-    picture::Picture,
+    config::WriteOptions,
+    file::FileType,
+    picture::{Picture, PictureType},
     prelude::*,
     probe::Probe,
-    tag::{Tag, TagType},
+    properties::FileProperties,
+    tag::{ItemValue, Tag, TagItem, TagType},
 };
 use serde::{Deserialize, Serialize};
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+use crate::template::PathTemplate;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 /// Track metadata field types
 pub enum Field {
     Cached { field: CachedField },
     Tag { key: ItemKey },
 }
 
+impl Field {
+    /// Human-readable name of the field
+    pub fn name(&self) -> String {
+        match self {
+            Field::Cached { field } => field.name().to_owned(),
+            Field::Tag {
+                key: ItemKey::Unknown(key),
+            } => key.clone(),
+            Field::Tag { key } => format!("{key:?}"),
+        }
+    }
+}
+
+impl From<ItemKey> for Field {
+    fn from(key: ItemKey) -> Self {
+        match CachedField::try_from(key.clone()) {
+            Ok(field) => Field::Cached { field },
+            Err(_) => Field::Tag { key },
+        }
+    }
+}
+
 #[non_exhaustive]
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 /// Track metadata fields that are stored in the cached database
 pub enum CachedField {
     Title,
     Artist,
     Album,
+    AlbumArtist,
+    DiscNumber,
+    DiscTotal,
+    TrackNumber,
+    TrackTotal,
     Year,
     Genre,
     Duration,
+    Codec,
+    Bitrate,
+    SampleRate,
+    BitDepth,
+    Channels,
+    /// Label of the library root the track was found in
+    Root,
+}
+
+impl CachedField {
+    /// Human-readable name, used for table headers
+    pub fn name(&self) -> &'static str {
+        match self {
+            CachedField::Title => "Title",
+            CachedField::Artist => "Artist",
+            CachedField::Album => "Album",
+            CachedField::AlbumArtist => "Album Artist",
+            CachedField::DiscNumber => "Disc",
+            CachedField::DiscTotal => "Discs",
+            CachedField::TrackNumber => "#",
+            CachedField::TrackTotal => "Tracks",
+            CachedField::Year => "Year",
+            CachedField::Genre => "Genre",
+            CachedField::Duration => "Duration",
+            CachedField::Codec => "Codec",
+            CachedField::Bitrate => "Bitrate",
+            CachedField::SampleRate => "Sample Rate",
+            CachedField::BitDepth => "Bit Depth",
+            CachedField::Channels => "Channels",
+            CachedField::Root => "Root",
+        }
+    }
 }
 
 impl TryFrom<ItemKey> for CachedField {
@@ -40,14 +108,13 @@ impl TryFrom<ItemKey> for CachedField {
     fn try_from(key: ItemKey) -> Result<Self, Self::Error> {
         match key {
             ItemKey::TrackTitle => Ok(Self::Title),
-            ItemKey::TrackArtist => Ok(Self::Artist),
-            // ItemKey::TrackArtists => todo!(),
+            ItemKey::TrackArtist | ItemKey::TrackArtists => Ok(Self::Artist),
             ItemKey::AlbumTitle => Ok(Self::Album),
-            // ItemKey::AlbumArtist => todo!(),
-            // ItemKey::DiscNumber => todo!(),
-            // ItemKey::DiscTotal => todo!(),
-            // ItemKey::TrackNumber => todo!(),
-            // ItemKey::TrackTotal => todo!(),
+            ItemKey::AlbumArtist => Ok(Self::AlbumArtist),
+            ItemKey::DiscNumber => Ok(Self::DiscNumber),
+            ItemKey::DiscTotal => Ok(Self::DiscTotal),
+            ItemKey::TrackNumber => Ok(Self::TrackNumber),
+            ItemKey::TrackTotal => Ok(Self::TrackTotal),
             ItemKey::Year => Ok(Self::Year),
             ItemKey::Genre => Ok(Self::Genre),
             _ => Err(eyre!("Unsupported field")),
@@ -63,6 +130,11 @@ impl TryFrom<&CachedField> for ItemKey {
             CachedField::Title => Ok(ItemKey::TrackTitle),
             CachedField::Artist => Ok(ItemKey::TrackArtist),
             CachedField::Album => Ok(ItemKey::AlbumTitle),
+            CachedField::AlbumArtist => Ok(ItemKey::AlbumArtist),
+            CachedField::DiscNumber => Ok(ItemKey::DiscNumber),
+            CachedField::DiscTotal => Ok(ItemKey::DiscTotal),
+            CachedField::TrackNumber => Ok(ItemKey::TrackNumber),
+            CachedField::TrackTotal => Ok(ItemKey::TrackTotal),
             CachedField::Year => Ok(ItemKey::Year),
             CachedField::Genre => Ok(ItemKey::Genre),
             _ => Err(eyre!("Unsupported field")),
@@ -70,26 +142,512 @@ impl TryFrom<&CachedField> for ItemKey {
     }
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Direction to sort a [`CachedField`] in
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    /// Returns the opposite direction
+    pub fn reversed(self) -> Self {
+        match self {
+            SortDirection::Ascending => SortDirection::Descending,
+            SortDirection::Descending => SortDirection::Ascending,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A field to sort by, along with the direction to sort it in
+pub struct SortKey {
+    pub field: CachedField,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+impl SortKey {
+    pub const fn ascending(field: CachedField) -> Self {
+        Self {
+            field,
+            direction: SortDirection::Ascending,
+        }
+    }
+
+    pub const fn descending(field: CachedField) -> Self {
+        Self {
+            field,
+            direction: SortDirection::Descending,
+        }
+    }
+
+    /// [`Track::ALBUM_ORDER`] as a list of ascending sort keys
+    pub fn album_order() -> Vec<Self> {
+        Track::ALBUM_ORDER
+            .iter()
+            .copied()
+            .map(Self::ascending)
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// A field's value, prepared so it can be compared without normalizing any text again
+enum FieldKey {
+    /// Collation key, then the raw text to break ties, with missing values first
+    Text(Option<(String, String)>),
+    Number(Option<u64>),
+    /// Whether the track has a title, then its title or filename, so untitled tracks come first
+    Title(bool, Option<(String, String)>),
+}
+
+#[derive(Debug)]
+/// The values a track is sorted by, from [`Track::sort_key`]
+pub struct TrackSortKey(Vec<(FieldKey, SortDirection)>);
+
+impl Ord for TrackSortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .fold(Ordering::Equal, |prev, ((a, direction), (b, _))| {
+                prev.then_with(|| match direction {
+                    SortDirection::Ascending => a.cmp(b),
+                    SortDirection::Descending => a.cmp(b).reverse(),
+                })
+            })
+    }
+}
+
+impl PartialOrd for TrackSortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TrackSortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for TrackSortKey {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Operator for comparing a numeric field in a [`TrackFilter`]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn matches(self, a: u32, b: u32) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Equal => a == b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Greater => a > b,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A search criterion on a track's audio properties or library root
+///
+/// Parsed from search terms like `is:lossless`, `is:lossy`, `codec:mp3`, `bitrate<192`,
+/// `samplerate>=96000`, `bitdepth>16`, `channels=1` or `root:nas`. Tracks missing the property
+/// being compared never match.
+pub enum TrackFilter {
+    Lossless,
+    Lossy,
+    Codec(String),
+    Root(String),
+    Compare {
+        field: CachedField,
+        comparison: Comparison,
+        value: u32,
+    },
+}
+
+impl TrackFilter {
+    /// Splits a search query into terms at whitespace, except inside double quotes
+    ///
+    /// The quotes are removed, so `root:"My NAS"` is the single term `root:My NAS`.
+    pub fn split_query(query: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        let mut term = String::new();
+        let mut quoted = false;
+        for c in query.chars() {
+            match c {
+                '"' => quoted = !quoted,
+                c if c.is_whitespace() && !quoted => {
+                    if !term.is_empty() {
+                        terms.push(std::mem::take(&mut term));
+                    }
+                }
+                c => term.push(c),
+            }
+        }
+        if !term.is_empty() {
+            terms.push(term);
+        }
+
+        terms
+    }
+
+    /// Splits a search query into the words to match and the filters to apply
+    pub fn parse_query(query: &str) -> (Vec<String>, Vec<Self>) {
+        let mut words = Vec::new();
+        let mut filters = Vec::new();
+        for term in Self::split_query(query) {
+            match Self::parse(&term) {
+                Some(filter) => filters.push(filter),
+                None => words.push(term),
+            }
+        }
+
+        (words, filters)
+    }
+
+    /// Parses a single search term, returning `None` if it isn't a filter
+    pub fn parse(term: &str) -> Option<Self> {
+        let term = term.to_lowercase();
+        match term.as_str() {
+            "is:lossless" => return Some(Self::Lossless),
+            "is:lossy" => return Some(Self::Lossy),
+            _ => {}
+        }
+        if let Some(codec) = term.strip_prefix("codec:") {
+            return (!codec.is_empty()).then(|| Self::Codec(codec.to_owned()));
+        }
+        if let Some(root) = term.strip_prefix("root:") {
+            return (!root.is_empty()).then(|| Self::Root(root.to_owned()));
+        }
+
+        let split = term.find(['<', '>', '='])?;
+        let (name, rest) = term.split_at(split);
+        let field = match name {
+            "bitrate" => CachedField::Bitrate,
+            "samplerate" => CachedField::SampleRate,
+            "bitdepth" => CachedField::BitDepth,
+            "channels" => CachedField::Channels,
+            _ => return None,
+        };
+        let (comparison, value) = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(operator, comparison)| {
+            rest.strip_prefix(operator).map(|value| (comparison, value))
+        })?;
+
+        Some(Self::Compare {
+            field,
+            comparison,
+            value: value.parse().ok()?,
+        })
+    }
+
+    /// Whether the track satisfies this filter
+    pub fn matches(&self, track: &Track) -> bool {
+        match self {
+            TrackFilter::Lossless => track.is_lossless(),
+            TrackFilter::Lossy => track.is_lossy(),
+            TrackFilter::Codec(codec) => track
+                .codec
+                .as_ref()
+                .is_some_and(|c| c.eq_ignore_ascii_case(codec)),
+            TrackFilter::Root(root) => track
+                .root
+                .as_ref()
+                .is_some_and(|r| r.to_lowercase() == *root),
+            TrackFilter::Compare {
+                field,
+                comparison,
+                value,
+            } => track
+                .audio_property(field)
+                .is_some_and(|property| comparison.matches(property, *value)),
+        }
+    }
+}
+
+/// Everything that can be read from a track's file, for display
+pub(crate) struct TrackDetails {
+    pub file_size: u64,
+    pub file_type: FileType,
+    pub properties: FileProperties,
+    /// Every item in every tag in the file
+    pub tags: Vec<(TagType, Vec<(Field, String)>)>,
+    /// Type, MIME type and size in bytes of each embedded picture
+    pub pictures: Vec<(PictureType, Option<String>, usize)>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Rules for comparing text when sorting tracks
+///
+/// Text is compared case-insensitively after Unicode normalization, ignoring diacritics, so
+/// "Édith Piaf" sorts next to "Edith". Strings that only differ in those respects are then
+/// ordered by their raw value so sorting stays deterministic.
+pub struct Collation {
+    /// Leading articles to ignore, e.g., "The" files "The Beatles" under B
+    ///
+    /// Articles ending in an apostrophe (e.g., "L'") don't need to be followed by a space.
+    pub ignored_articles: Vec<String>,
+}
+
+impl Collation {
+    /// Compares two optional strings, sorting missing values first
+    pub fn cmp(&self, a: Option<&str>, b: Option<&str>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => self.key(a).cmp(&self.key(b)).then_with(|| a.cmp(b)),
+            _ => a.cmp(&b),
+        }
+    }
+
+    /// The string that `s` should be sorted by
+    pub fn key(&self, s: &str) -> String {
+        let folded = Self::fold(s);
+        let trimmed = folded.trim_start();
+
+        for article in &self.ignored_articles {
+            let article = Self::fold(article);
+            if let Some(rest) = trimmed.strip_prefix(article.as_str()) {
+                if article.ends_with('\'') && !rest.is_empty() {
+                    return rest.to_owned();
+                }
+                if rest.starts_with(char::is_whitespace) && !rest.trim_start().is_empty() {
+                    return rest.trim_start().to_owned();
+                }
+            }
+        }
+
+        trimmed.to_owned()
+    }
+
+    /// The collation key and raw text of an optional string, which order the same way as
+    /// [`Collation::cmp`]
+    fn text_key(&self, s: Option<&str>) -> Option<(String, String)> {
+        s.map(|s| (self.key(s), s.to_owned()))
+    }
+
+    /// Normalizes to NFKD, then strips combining marks and lowercases
+    fn fold(s: &str) -> String {
+        s.nfkd()
+            .filter(|c| !is_combining_mark(*c))
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+}
+
 #[non_exhaustive]
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 /// A track from a file
 pub struct Track {
     pub(crate) path: PathBuf,
-    title: Option<String>,
-    artist: Option<String>,
-    album: Option<String>,
-    pub duration: u64,
+    pub(crate) title: Option<String>,
+    /// All artists on the track, e.g., from multi-value tags
+    #[serde(rename = "artist", with = "multi_value", default)]
+    pub(crate) artists: Vec<String>,
+    pub(crate) album: Option<String>,
+    pub(crate) album_artist: Option<String>,
+    pub(crate) disc_number: Option<u32>,
+    pub(crate) disc_total: Option<u32>,
+    pub(crate) track_number: Option<u32>,
+    pub(crate) track_total: Option<u32>,
+    pub(crate) year: Option<u32>,
+    pub(crate) genre: Option<String>,
+    pub(crate) title_sort: Option<String>,
+    pub(crate) artist_sort: Option<String>,
+    pub(crate) album_sort: Option<String>,
+    pub(crate) album_artist_sort: Option<String>,
+    #[serde(rename = "duration_ms", with = "milliseconds")]
+    pub duration: Duration,
+    /// Audio codec, e.g., "FLAC" or "MP3"
+    pub(crate) codec: Option<String>,
+    /// Audio bitrate in kbps
+    pub(crate) bitrate: Option<u32>,
+    /// Sample rate in Hz
+    pub(crate) sample_rate: Option<u32>,
+    pub(crate) bit_depth: Option<u8>,
+    pub(crate) channels: Option<u8>,
+    /// Modification time of the file when it was read, in milliseconds since the Unix epoch
+    #[serde(default)]
+    pub(crate) file_modified: u64,
+    /// Size of the file in bytes when it was read
+    #[serde(default)]
+    pub(crate) file_size: u64,
+    /// Label of the library root the track was found in
+    #[serde(default)]
+    pub(crate) root: Option<String>,
+    /// Fields that were missing from the tags and filled in from the path
+    #[serde(default, with = "field_list")]
+    pub(crate) inferred: Vec<CachedField>,
 }
 
 impl Track {
+    /// Sort order that keeps albums together and plays them in their running order
+    ///
+    /// Albums are grouped by album artist (falling back to the track artist) and ordered
+    /// chronologically, then tracks are ordered by disc and track number. Titles are only used
+    /// to break ties, e.g., for files without track numbers.
+    pub const ALBUM_ORDER: &[CachedField] = &[
+        CachedField::AlbumArtist,
+        CachedField::Year,
+        CachedField::Album,
+        CachedField::DiscNumber,
+        CachedField::TrackNumber,
+        CachedField::Title,
+    ];
+
+    /// Codecs that don't discard any audio information
+    pub const LOSSLESS_CODECS: &[&str] = &["AIFF", "ALAC", "APE", "FLAC", "WAV", "WavPack"];
+
+    /// Codec name for files lofty doesn't recognize, which may or may not be lossy
+    pub const UNKNOWN_CODEC: &str = "Unknown";
+
+    /// Separator used when displaying or editing fields with multiple values
+    pub(crate) const MULTI_VALUE_SEPARATOR: &str = "; ";
+
     fn tag_to_string(tag: Option<Cow<str>>) -> Option<String> {
         tag.as_deref().map(|x| x.to_owned())
     }
 
-    pub(crate) fn format_duration(secs: u64) -> String {
-        let mins = secs / 60;
+    /// Every value of a possibly multi-valued field
+    ///
+    /// Handles values stored as separate items (e.g., repeated Vorbis comments or MP4 atoms) as
+    /// well as ID3v2.4's null-separated values.
+    fn tag_strings(tag: &Tag, key: &ItemKey) -> Vec<String> {
+        let mut values: Vec<String> = Vec::new();
+        for value in tag.get_strings(key).flat_map(|s| s.split('\0')) {
+            let value = value.trim();
+            if !value.is_empty() && !values.iter().any(|v| v == value) {
+                values.push(value.to_owned());
+            }
+        }
+
+        values
+    }
+
+    /// All artists on the track
+    pub(crate) fn artists(&self) -> &[String] {
+        &self.artists
+    }
+
+    /// Whether the file still has the modification time and size it had when it was read
+    pub(crate) fn matches_file(&self, metadata: &Metadata) -> bool {
+        self.file_size == metadata.len() && self.file_modified == Self::modified_millis(metadata)
+    }
+
+    fn modified_millis(metadata: &Metadata) -> u64 {
+        metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |time| time.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    /// Whether the track is encoded with one of the [`Track::LOSSLESS_CODECS`]
+    pub fn is_lossless(&self) -> bool {
+        self.codec
+            .as_deref()
+            .is_some_and(|codec| Self::LOSSLESS_CODECS.contains(&codec))
+    }
+
+    /// Whether the track's codec is known and isn't one of the [`Track::LOSSLESS_CODECS`]
+    pub fn is_lossy(&self) -> bool {
+        self.codec
+            .as_deref()
+            .is_some_and(|codec| codec != Self::UNKNOWN_CODEC)
+            && !self.is_lossless()
+    }
+
+    /// The value of a numeric audio property, e.g., the bitrate
+    fn audio_property(&self, field: &CachedField) -> Option<u32> {
+        match field {
+            CachedField::Bitrate => self.bitrate,
+            CachedField::SampleRate => self.sample_rate,
+            CachedField::BitDepth => self.bit_depth.map(u32::from),
+            CachedField::Channels => self.channels.map(u32::from),
+            _ => None,
+        }
+    }
+
+    /// Display name of the codec used by a file
+    pub(crate) fn codec_name(file_type: &FileType, properties: &FileProperties) -> String {
+        match file_type {
+            FileType::Aac => "AAC",
+            FileType::Aiff => "AIFF",
+            FileType::Ape => "APE",
+            FileType::Flac => "FLAC",
+            FileType::Mpeg => "MP3",
+            // MP4 files can hold either, but only lossless streams have a bit depth
+            FileType::Mp4 if properties.bit_depth().is_some() => "ALAC",
+            FileType::Mp4 => "AAC",
+            FileType::Mpc => "Musepack",
+            FileType::Opus => "Opus",
+            FileType::Vorbis => "Vorbis",
+            FileType::Speex => "Speex",
+            FileType::Wav => "WAV",
+            FileType::WavPack => "WavPack",
+            FileType::Custom(name) => name,
+            _ => Self::UNKNOWN_CODEC,
+        }
+        .to_owned()
+    }
+
+    /// Formats a duration as, e.g., "3:07", or "1:02:03" if it's over an hour long
+    pub(crate) fn format_duration(duration: Duration) -> String {
+        let secs = duration.as_secs();
+        let hours = secs / 3600;
+        let mins = secs / 60 % 60;
         let secs = secs % 60;
-        format!("{mins}:{:0>2}", secs)
+        if hours > 0 {
+            format!("{hours}:{mins:0>2}:{secs:0>2}")
+        } else {
+            format!("{mins}:{secs:0>2}")
+        }
+    }
+
+    /// Parses a time like "3:07", "1:02:03" or "90", the inverse of [`Track::format_duration`]
+    pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+        let parts: Vec<_> = text.trim().split(':').collect();
+        if parts.len() > 3 {
+            return None;
+        }
+
+        let mut secs = 0;
+        for (index, part) in parts.iter().enumerate() {
+            let value: u64 = part.parse().ok()?;
+            // Only the first part can go past 59, e.g., "90:00" for an hour and a half
+            if index > 0 && (value >= 60 || part.len() != 2) {
+                return None;
+            }
+            secs = secs * 60 + value;
+        }
+
+        Some(Duration::from_secs(secs))
+    }
+
+    /// How far through the track a playback position is, from 0 to 1
+    ///
+    /// Always finite, even for empty tracks or files whose headers report the wrong length.
+    pub(crate) fn progress(&self, position: Duration) -> f64 {
+        if self.duration.is_zero() {
+            return 0.0;
+        }
+
+        (position.as_secs_f64() / self.duration.as_secs_f64()).clamp(0.0, 1.0)
     }
 
     pub(crate) fn cached_field_string(&self, field: &CachedField) -> String {
@@ -105,34 +663,225 @@ impl Track {
                         .into_owned()
                 }
             }
-            CachedField::Artist => self.artist.clone().unwrap_or_default(),
+            CachedField::Artist => self.artists.join(Self::MULTI_VALUE_SEPARATOR),
+            CachedField::Album => self.album.clone().unwrap_or_default(),
+            CachedField::AlbumArtist => self.album_artist.clone().unwrap_or_default(),
+            CachedField::DiscNumber => Self::number_to_string(self.disc_number),
+            CachedField::DiscTotal => Self::number_to_string(self.disc_total),
+            CachedField::TrackNumber => Self::number_to_string(self.track_number),
+            CachedField::TrackTotal => Self::number_to_string(self.track_total),
+            CachedField::Year => Self::number_to_string(self.year),
+            CachedField::Genre => self.genre.clone().unwrap_or_default(),
             CachedField::Duration => Self::format_duration(self.duration),
-            _ => {
-                if let Ok(key) = field.try_into() {
-                    if let Ok(s) = self.tag_string_from_track(key) {
-                        s
-                    } else {
-                        "".to_owned()
+            CachedField::Codec => self.codec.clone().unwrap_or_default(),
+            CachedField::Bitrate => self
+                .bitrate
+                .map(|bitrate| format!("{bitrate} kbps"))
+                .unwrap_or_default(),
+            CachedField::SampleRate => self
+                .sample_rate
+                .map(|rate| format!("{} kHz", rate as f64 / 1000.0))
+                .unwrap_or_default(),
+            CachedField::BitDepth => self
+                .bit_depth
+                .map(|depth| format!("{depth}-bit"))
+                .unwrap_or_default(),
+            CachedField::Channels => Self::number_to_string(self.channels.map(u32::from)),
+            CachedField::Root => self.root.clone().unwrap_or_default(),
+        }
+    }
+
+    fn number_to_string(number: Option<u32>) -> String {
+        number.map(|n| n.to_string()).unwrap_or_default()
+    }
+
+    /// Fills in fields missing from the tags using the first template that matches the track's
+    /// path within `root`
+    ///
+    /// Values inferred before are cleared first, so changes to the templates are picked up.
+    pub(crate) fn infer_from_path(&mut self, root: &Path, templates: &[PathTemplate]) {
+        for field in std::mem::take(&mut self.inferred) {
+            self.set_field(&field, None);
+        }
+
+        let Ok(relative) = self.path.strip_prefix(root) else {
+            return;
+        };
+        let Some(captures) = templates
+            .iter()
+            .find_map(|template| template.captures(relative))
+        else {
+            return;
+        };
+        for (field, value) in captures {
+            if self.is_missing(&field) {
+                self.set_field(&field, Some(value));
+                self.inferred.push(field);
+            }
+        }
+    }
+
+    /// Whether a field that can be inferred from the path is missing from the tags
+    pub(crate) fn is_missing(&self, field: &CachedField) -> bool {
+        match field {
+            CachedField::Title => self.title.is_none(),
+            CachedField::Artist => self.artists.is_empty(),
+            CachedField::Album => self.album.is_none(),
+            CachedField::AlbumArtist => self.album_artist.is_none(),
+            CachedField::DiscNumber => self.disc_number.is_none(),
+            CachedField::TrackNumber => self.track_number.is_none(),
+            CachedField::Year => self.year.is_none(),
+            CachedField::Genre => self.genre.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Sets a field that can be inferred from the path, or clears it if `value` is `None`
+    fn set_field(&mut self, field: &CachedField, value: Option<String>) {
+        let number = value.as_deref().and_then(|value| value.parse().ok());
+        match field {
+            CachedField::Title => self.title = value,
+            CachedField::Artist => self.artists = value.into_iter().collect(),
+            CachedField::Album => self.album = value,
+            CachedField::AlbumArtist => self.album_artist = value,
+            CachedField::DiscNumber => self.disc_number = number,
+            CachedField::TrackNumber => self.track_number = number,
+            CachedField::Year => self.year = number,
+            CachedField::Genre => self.genre = value,
+            _ => {}
+        }
+    }
+
+    /// A primary tag of type `tag_type` for a file that doesn't have one yet
+    ///
+    /// Tracks are read from the primary tag whenever a file has one, so it starts out with the
+    /// fields from the file's other tag, e.g., ID3v1 in an MP3, rather than hiding them.
+    fn new_primary_tag(tag_type: TagType, other: Option<&Tag>) -> Tag {
+        let mut tag = other.cloned().unwrap_or_else(|| Tag::new(tag_type));
+        tag.re_tag(tag_type);
+        tag
+    }
+
+    /// Writes the given field values to the file's primary tag, then re-reads the track
+    ///
+    /// Empty values remove the field from the tag. Fails without writing anything if a value
+    /// can't be parsed for its field.
+    pub(crate) fn write_tags(&mut self, edits: &[(CachedField, String)]) -> Result<()> {
+        let mut tagged_file = Probe::open(&self.path)?.read()?;
+        if tagged_file.primary_tag().is_none() {
+            let tag =
+                Self::new_primary_tag(tagged_file.primary_tag_type(), tagged_file.first_tag());
+            tagged_file.insert_tag(tag);
+        }
+        let tag = tagged_file
+            .primary_tag_mut()
+            .ok_or(eyre!("Couldn't create tag"))?;
+
+        for (field, value) in edits {
+            let value = value.trim();
+            let number = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| eyre!("{} must be a number, got '{value}'", field.name()))
+            };
+
+            match (field, value.is_empty()) {
+                (CachedField::Title, true) => tag.remove_title(),
+                (CachedField::Title, false) => tag.set_title(value.to_owned()),
+                (CachedField::Artist, _) => {
+                    // Replace any separate list of artists too, since it takes priority when
+                    // reading tags
+                    tag.remove_key(&ItemKey::TrackArtists);
+                    tag.remove_artist();
+                    let artists = value
+                        .split(Self::MULTI_VALUE_SEPARATOR.trim())
+                        .map(str::trim)
+                        .filter(|artist| !artist.is_empty());
+                    for artist in artists {
+                        tag.push(TagItem::new(
+                            ItemKey::TrackArtist,
+                            ItemValue::Text(artist.to_owned()),
+                        ));
                     }
-                } else {
-                    "".to_owned()
                 }
+                (CachedField::Album, true) => tag.remove_album(),
+                (CachedField::Album, false) => tag.set_album(value.to_owned()),
+                (CachedField::Genre, true) => tag.remove_genre(),
+                (CachedField::Genre, false) => tag.set_genre(value.to_owned()),
+                (CachedField::Year, true) => tag.remove_year(),
+                (CachedField::Year, false) => tag.set_year(number()?),
+                (CachedField::TrackNumber, true) => tag.remove_track(),
+                (CachedField::TrackNumber, false) => tag.set_track(number()?),
+                (CachedField::DiscNumber, true) => tag.remove_disk(),
+                (CachedField::DiscNumber, false) => tag.set_disk(number()?),
+                (CachedField::AlbumArtist, true) => tag.remove_key(&ItemKey::AlbumArtist),
+                (CachedField::AlbumArtist, false) => {
+                    tag.insert_text(ItemKey::AlbumArtist, value.to_owned());
+                }
+                _ => return Err(eyre!("Editing {} is not supported", field.name())),
             }
         }
+
+        tag.save_to_path(&self.path, WriteOptions::default())?;
+        let mut track = Self::try_from(self.path.clone())?;
+        track.root = self.root.clone();
+        // Keep values inferred from the path for fields that are still missing from the tags
+        for field in &self.inferred {
+            if track.is_missing(field) {
+                track.set_field(field, Some(self.cached_field_string(field)));
+                track.inferred.push(*field);
+            }
+        }
+        *self = track;
+
+        Ok(())
     }
 
-    pub(crate) fn tag_string_from_track(&self, key: ItemKey) -> Result<String> {
+    /// Reads all tags, pictures and audio properties from the file
+    pub(crate) fn details(&self) -> Result<TrackDetails> {
+        let file_size = std::fs::metadata(&self.path)?.len();
         let tagged_file = Probe::open(&self.path)?.read()?;
 
-        let tag = tagged_file
-            .primary_tag()
-            .or_else(|| tagged_file.first_tag())
-            .ok_or(eyre!("Couldn't find tag"))?;
+        let tags = tagged_file
+            .tags()
+            .iter()
+            .map(|tag| {
+                let items = tag
+                    .items()
+                    .map(|item| {
+                        let value = match item.value() {
+                            ItemValue::Text(text) | ItemValue::Locator(text) => text.clone(),
+                            ItemValue::Binary(data) => {
+                                format!("<{} bytes of binary data>", data.len())
+                            }
+                        };
+                        (Field::from(item.key().clone()), value)
+                    })
+                    .collect();
+                (tag.tag_type(), items)
+            })
+            .collect();
+
+        let pictures = tagged_file
+            .tags()
+            .iter()
+            .flat_map(|tag| tag.pictures())
+            .map(|picture| {
+                (
+                    picture.pic_type(),
+                    picture.mime_type().map(|mime| mime.as_str().to_owned()),
+                    picture.data().len(),
+                )
+            })
+            .collect();
 
-        Ok(tag
-            .get_string(&key)
-            .ok_or(eyre!("Couldn't get string for {key:?}"))?
-            .to_owned())
+        Ok(TrackDetails {
+            file_size,
+            file_type: tagged_file.file_type(),
+            properties: tagged_file.properties().clone(),
+            tags,
+            pictures,
+        })
     }
 
     fn pictures(&self) -> Result<Vec<Picture>> {
@@ -146,16 +895,69 @@ impl Track {
         Ok(tag.pictures().to_vec())
     }
 
-    pub(crate) async fn track_art_as_dynamic_image(&self) -> Result<DynamicImage> {
-        let pictures = self.pictures()?;
-        let picture = pictures.first().ok_or(eyre!("No pictures found!"))?;
-        let cursor = Cursor::new(picture.data());
-        let decoder = ImageReader::new(cursor).with_guessed_format()?;
-        let image = decoder.decode()?;
-        Ok(image)
+    /// Image files next to the track whose names match the given patterns
+    ///
+    /// Files are returned in the order of the patterns they match, and matching is
+    /// case-insensitive, so `cover.*` finds both `cover.jpg` and `Cover.PNG`.
+    fn sidecar_images(&self, patterns: &[Pattern]) -> Vec<PathBuf> {
+        let Some(Ok(entries)) = self.path.parent().map(std::fs::read_dir) else {
+            return Vec::new();
+        };
+        let mut images: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && ImageFormat::from_path(path).is_ok())
+            .collect();
+        images.sort();
+
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        patterns
+            .iter()
+            .flat_map(|pattern| {
+                images.iter().filter(move |path| {
+                    path.file_name()
+                        .is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), options))
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Loads the track's art, preferring the embedded front cover, then any embedded picture,
+    /// then the first image file next to the track that matches `sidecar_patterns`
+    ///
+    /// Pictures that can't be decoded are skipped, so a corrupt embedded picture doesn't hide
+    /// the sidecar images.
+    pub(crate) async fn track_art_as_dynamic_image(
+        &self,
+        sidecar_patterns: &[Pattern],
+    ) -> Result<DynamicImage> {
+        let mut pictures = self.pictures().unwrap_or_default();
+        // Stable, so other pictures stay in the order they're stored in
+        pictures.sort_by_key(|picture| picture.pic_type() != PictureType::CoverFront);
+        for picture in &pictures {
+            if let Ok(image) = ImageReader::new(Cursor::new(picture.data())).with_guessed_format()
+                && let Ok(image) = image.decode()
+            {
+                return Ok(image);
+            }
+        }
+
+        for path in self.sidecar_images(sidecar_patterns) {
+            if let Ok(image) = ImageReader::open(&path).and_then(|r| r.with_guessed_format())
+                && let Ok(image) = image.decode()
+            {
+                return Ok(image);
+            }
+        }
+
+        Err(eyre!("No pictures found!"))
     }
 
-    /// Orders two tracks based on a given list of fields
+    /// Orders two tracks based on a given list of fields, sorting each in ascending order
     ///
     /// Useful for sorting, e.g.,
     ///
@@ -170,35 +972,185 @@ impl Track {
     ///     )
     /// });
     /// ```
-    // Adapted from https://stackoverflow.com/questions/46512227/sort-a-vector-with-a-comparator-which-changes-its-behavior-dynamically/46514082#46514082
-    // TODO: Allow inverting the sort
     pub fn compare_by_fields(a: &Self, b: &Self, fields: &[CachedField]) -> Ordering {
-        fields.iter().fold(Ordering::Equal, |prev, &field| {
-            prev.then_with(|| match field {
-                CachedField::Title => match (&a.title, &b.title) {
-                    (Some(_), Some(_)) => Self::case_insensitive_cmp(&a.title, &b.title),
-                    (Some(_), None) => Ordering::Greater,
-                    (None, Some(_)) => Ordering::Less,
-                    (None, None) => Self::case_insensitive_cmp(
-                        &Some(a.cached_field_string(&CachedField::Title)),
-                        &Some(b.cached_field_string(&CachedField::Title)),
-                    ),
-                },
-                CachedField::Artist => Self::case_insensitive_cmp(&a.artist, &b.artist),
-                CachedField::Album => Self::case_insensitive_cmp(&a.album, &b.album),
-                // CachedField::Year => todo!(),
-                // CachedField::Genre => todo!(),
-                CachedField::Duration => a.duration.cmp(&b.duration),
-                _ => Ordering::Equal,
-            })
+        let collation = Collation::default();
+        fields.iter().fold(Ordering::Equal, |prev, field| {
+            prev.then_with(|| Self::compare_by_field(a, b, field, &collation))
         })
     }
 
-    fn case_insensitive_cmp(a: &Option<String>, b: &Option<String>) -> Ordering {
-        Self::option_map_cmp(a.as_ref(), b.as_ref(), |s| s.to_lowercase())
+    /// Orders two tracks based on a given list of [`SortKey`]s, comparing text using the given
+    /// [`Collation`]
+    ///
+    /// Each comparison normalizes the tracks' text again, so use [`Track::sort_key`] to sort
+    /// whole lists.
+    ///
+    /// ```
+    /// # use minim::track::{CachedField, Collation, SortKey, Track};
+    /// # let mut tracks = vec![];
+    /// let collation = Collation {
+    ///     ignored_articles: vec!["The".to_owned()],
+    /// };
+    /// tracks.sort_by(|a, b| {
+    ///     Track::compare_by_sort_keys(
+    ///         a,
+    ///         b,
+    ///         &[
+    ///             SortKey::descending(CachedField::Year),
+    ///             SortKey::ascending(CachedField::Title),
+    ///         ],
+    ///         &collation,
+    ///     )
+    /// });
+    /// ```
+    // Adapted from https://stackoverflow.com/questions/46512227/sort-a-vector-with-a-comparator-which-changes-its-behavior-dynamically/46514082#46514082
+    pub fn compare_by_sort_keys(
+        a: &Self,
+        b: &Self,
+        keys: &[SortKey],
+        collation: &Collation,
+    ) -> Ordering {
+        a.sort_key(keys, collation)
+            .cmp(&b.sort_key(keys, collation))
+    }
+
+    /// The values to sort the track by for a given list of [`SortKey`]s
+    ///
+    /// Meant for [`slice::sort_by_cached_key`], so each track's text is only normalized once
+    /// per sort.
+    ///
+    /// ```
+    /// # use minim::track::{Collation, SortKey, Track};
+    /// # let mut tracks: Vec<Track> = vec![];
+    /// let collation = Collation::default();
+    /// tracks.sort_by_cached_key(|track| track.sort_key(&SortKey::album_order(), &collation));
+    /// ```
+    pub fn sort_key(&self, keys: &[SortKey], collation: &Collation) -> TrackSortKey {
+        TrackSortKey(
+            keys.iter()
+                .map(|key| (self.field_key(&key.field, collation), key.direction))
+                .collect(),
+        )
+    }
+
+    /// A text field's sort key as a single string that orders the same way as
+    /// [`Track::sort_key`], so it can be stored in an indexed database column
+    ///
+    /// Returns `None` for numeric fields, and for text fields the track doesn't have.
+    pub(crate) fn stored_sort_key(
+        &self,
+        field: &CachedField,
+        collation: &Collation,
+    ) -> Option<String> {
+        // The collation key and raw text are separated by a character that sorts before any
+        // other, so a shorter key still comes first
+        match self.field_key(field, collation) {
+            FieldKey::Text(key) => key.map(|(key, text)| format!("{key}\0{text}")),
+            // Untitled tracks come first
+            FieldKey::Title(titled, key) => {
+                key.map(|(key, text)| format!("{}{key}\0{text}", u8::from(titled)))
+            }
+            FieldKey::Number(_) => None,
+        }
+    }
+
+    fn compare_by_field(
+        a: &Self,
+        b: &Self,
+        field: &CachedField,
+        collation: &Collation,
+    ) -> Ordering {
+        a.field_key(field, collation)
+            .cmp(&b.field_key(field, collation))
+    }
+
+    fn field_key(&self, field: &CachedField, collation: &Collation) -> FieldKey {
+        let number = |n: Option<u32>| FieldKey::Number(n.map(u64::from));
+        match field {
+            CachedField::Title => {
+                let title = match &self.title {
+                    Some(_) => collation.text_key(self.title_sort_name()),
+                    None => collation.text_key(Some(&self.cached_field_string(field))),
+                };
+                FieldKey::Title(self.title.is_some(), title)
+            }
+            CachedField::Artist => FieldKey::Text(collation.text_key(self.artist_sort_name())),
+            CachedField::Album => FieldKey::Text(collation.text_key(self.album_sort_name())),
+            CachedField::AlbumArtist => {
+                FieldKey::Text(collation.text_key(self.album_artist_sort_name()))
+            }
+            CachedField::DiscNumber => number(self.disc_number),
+            CachedField::DiscTotal => number(self.disc_total),
+            CachedField::TrackNumber => number(self.track_number),
+            CachedField::TrackTotal => number(self.track_total),
+            CachedField::Year => number(self.year),
+            CachedField::Genre => FieldKey::Text(collation.text_key(self.genre.as_deref())),
+            CachedField::Duration => FieldKey::Number(Some(
+                self.duration.as_millis().try_into().unwrap_or(u64::MAX),
+            )),
+            CachedField::Codec => FieldKey::Text(collation.text_key(self.codec.as_deref())),
+            CachedField::Bitrate
+            | CachedField::SampleRate
+            | CachedField::BitDepth
+            | CachedField::Channels => number(self.audio_property(field)),
+            CachedField::Root => FieldKey::Text(collation.text_key(self.root.as_deref())),
+        }
     }
 
-    fn option_map_cmp<T, U>(a: Option<&T>, b: Option<&T>, f: fn(&T) -> U) -> Ordering
+    // Sort tags (e.g., TITLESORT) take priority over the displayed value when present
+
+    fn title_sort_name(&self) -> Option<&str> {
+        self.title_sort.as_deref().or(self.title.as_deref())
+    }
+
+    fn artist_sort_name(&self) -> Option<&str> {
+        self.artist_sort
+            .as_deref()
+            .or(self.artists.first().map(String::as_str))
+    }
+
+    fn album_sort_name(&self) -> Option<&str> {
+        self.album_sort.as_deref().or(self.album.as_deref())
+    }
+
+    fn album_artist_sort_name(&self) -> Option<&str> {
+        if self.album_artist.is_some() {
+            self.album_artist_sort
+                .as_deref()
+                .or(self.album_artist.as_deref())
+        } else {
+            self.artist_sort_name()
+        }
+    }
+
+    /// The album artist, or the first track artist if the album artist tag is missing
+    fn album_artist_or_artist(&self) -> Option<&str> {
+        self.album_artist
+            .as_deref()
+            .or(self.artists.first().map(String::as_str))
+    }
+
+    /// Whether two tracks belong to the same album
+    ///
+    /// Tracks without an album tag are only considered to be on the same album as themselves.
+    pub(crate) fn same_album(&self, other: &Self) -> bool {
+        if self.album.is_none() {
+            return self == other;
+        }
+
+        Self::case_insensitive_cmp(self.album.as_deref(), other.album.as_deref()).is_eq()
+            && Self::case_insensitive_cmp(
+                self.album_artist_or_artist(),
+                other.album_artist_or_artist(),
+            )
+            .is_eq()
+    }
+
+    fn case_insensitive_cmp(a: Option<&str>, b: Option<&str>) -> Ordering {
+        Self::option_map_cmp(a, b, |s| s.to_lowercase())
+    }
+
+    fn option_map_cmp<T: ?Sized, U>(a: Option<&T>, b: Option<&T>, f: fn(&T) -> U) -> Ordering
     where
         U: Ord,
     {
@@ -206,6 +1158,84 @@ impl Track {
     }
 }
 
+/// (De)serializes a [`Duration`] as a whole number of milliseconds
+mod milliseconds {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+/// (De)serializes a list of fields as a single space-separated string, since CSV fields can't
+/// hold lists
+pub(crate) mod field_list {
+    use serde::{
+        Deserialize, Deserializer, Serializer,
+        de::{IntoDeserializer, value::StrDeserializer},
+    };
+
+    use super::CachedField;
+
+    pub fn to_string(fields: &[CachedField]) -> String {
+        fields
+            .iter()
+            .map(|field| format!("{field:?}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Parses a list written by [`to_string`], skipping unknown fields
+    pub fn from_str(s: &str) -> Vec<CachedField> {
+        s.split_whitespace()
+            .filter_map(|name| {
+                let deserializer: StrDeserializer<serde::de::value::Error> =
+                    name.into_deserializer();
+                CachedField::deserialize(deserializer).ok()
+            })
+            .collect()
+    }
+
+    pub fn serialize<S: Serializer>(
+        fields: &[CachedField],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_string(fields))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<CachedField>, D::Error> {
+        Ok(from_str(&String::deserialize(deserializer)?))
+    }
+}
+
+/// (De)serializes a list of values as a single null-separated string, like ID3v2.4 does, since
+/// CSV fields can't hold lists
+mod multi_value {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(values: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&values.join("\0"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(s.split('\0')
+            .filter(|value| !value.is_empty())
+            .map(str::to_owned)
+            .collect())
+    }
+}
+
 impl PartialEq for Track {
     fn eq(&self, other: &Self) -> bool {
         self.path.eq(&other.path)
@@ -234,6 +1264,7 @@ impl TryFrom<PathBuf> for Track {
     type Error = color_eyre::Report;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        let metadata = std::fs::metadata(&path)?;
         let tagged_file = Probe::open(&path)?.read()?;
 
         // Try to get primary tag, then try to find the first tag, otherwise
@@ -246,14 +1277,350 @@ impl TryFrom<PathBuf> for Track {
 
         let properties = tagged_file.properties();
 
+        // Prefer the dedicated list of artists if there is one, since the main artist field is
+        // often a single display string like "A feat. B"
+        let artists = match Self::tag_strings(&tag, &ItemKey::TrackArtists) {
+            artists if !artists.is_empty() => artists,
+            _ => Self::tag_strings(&tag, &ItemKey::TrackArtist),
+        };
+
         Ok({
             Track {
                 path,
                 title: Self::tag_to_string(tag.title()),
-                artist: Self::tag_to_string(tag.artist()),
+                artists,
                 album: Self::tag_to_string(tag.album()),
-                duration: properties.duration().as_secs(),
+                album_artist: tag.get_string(&ItemKey::AlbumArtist).map(str::to_owned),
+                disc_number: tag.disk(),
+                disc_total: tag.disk_total(),
+                track_number: tag.track(),
+                track_total: tag.track_total(),
+                year: tag.year(),
+                genre: Self::tag_to_string(tag.genre()),
+                title_sort: tag
+                    .get_string(&ItemKey::TrackTitleSortOrder)
+                    .map(str::to_owned),
+                artist_sort: tag
+                    .get_string(&ItemKey::TrackArtistSortOrder)
+                    .map(str::to_owned),
+                album_sort: tag
+                    .get_string(&ItemKey::AlbumTitleSortOrder)
+                    .map(str::to_owned),
+                album_artist_sort: tag
+                    .get_string(&ItemKey::AlbumArtistSortOrder)
+                    .map(str::to_owned),
+                duration: properties.duration(),
+                codec: Some(Self::codec_name(&tagged_file.file_type(), properties)),
+                bitrate: properties.audio_bitrate(),
+                sample_rate: properties.sample_rate(),
+                bit_depth: properties.bit_depth(),
+                channels: properties.channels(),
+                file_modified: Self::modified_millis(&metadata),
+                file_size: metadata.len(),
+                root: None,
+                inferred: Vec::new(),
             }
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn track(path: &str, album: &str, disc: Option<u32>, number: Option<u32>) -> Track {
+        Track {
+            path: PathBuf::from(path),
+            title: Some(path.to_owned()),
+            artists: vec!["Artist".to_owned()],
+            album: Some(album.to_owned()),
+            disc_number: disc,
+            track_number: number,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn new_primary_tags_keep_the_other_tags_fields() {
+        let mut id3v1 = Tag::new(TagType::Id3v1);
+        id3v1.set_title("Title".to_owned());
+        id3v1.set_artist("Artist".to_owned());
+
+        let tag = Track::new_primary_tag(TagType::Id3v2, Some(&id3v1));
+        assert_eq!(tag.tag_type(), TagType::Id3v2);
+        assert_eq!(tag.title().as_deref(), Some("Title"));
+        assert_eq!(tag.artist().as_deref(), Some("Artist"));
+
+        let empty = Track::new_primary_tag(TagType::Id3v2, None);
+        assert_eq!(empty.tag_type(), TagType::Id3v2);
+        assert_eq!(empty.title(), None);
+    }
+
+    #[test]
+    fn album_order_uses_disc_and_track_numbers() {
+        let mut tracks = [
+            track("a", "Album", Some(2), Some(1)),
+            track("b", "Album", Some(1), Some(2)),
+            track("c", "Album", Some(1), Some(1)),
+        ];
+        tracks.sort_by(|a, b| Track::compare_by_fields(a, b, Track::ALBUM_ORDER));
+
+        let paths: Vec<_> = tracks.iter().map(|t| t.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn descending_sort_key_reverses_field() {
+        let mut tracks = [
+            track("a", "Album", None, Some(1)),
+            track("b", "Album", None, Some(3)),
+            track("c", "Album", None, Some(2)),
+        ];
+        tracks.sort_by(|a, b| {
+            Track::compare_by_sort_keys(
+                a,
+                b,
+                &[SortKey::descending(CachedField::TrackNumber)],
+                &Collation::default(),
+            )
+        });
+
+        let paths: Vec<_> = tracks.iter().map(|t| t.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn cached_sort_keys_match_comparisons() {
+        let collation = Collation {
+            ignored_articles: vec!["The".to_owned()],
+        };
+        let keys = [
+            SortKey::descending(CachedField::Artist),
+            SortKey::ascending(CachedField::Title),
+        ];
+        let mut untitled = track("d", "Album", None, None);
+        untitled.title = None;
+        let mut tracks = vec![
+            track("b", "Album", None, None),
+            track("a", "Album", None, None),
+            untitled,
+        ];
+        tracks[0].artists = vec!["The Zombies".to_owned()];
+        tracks[1].artists = vec!["Édith Piaf".to_owned()];
+        tracks[2].artists = vec!["Édith Piaf".to_owned()];
+
+        let mut compared = tracks.clone();
+        compared.sort_by(|a, b| Track::compare_by_sort_keys(a, b, &keys, &collation));
+        tracks.sort_by_cached_key(|track| track.sort_key(&keys, &collation));
+
+        assert_eq!(tracks, compared);
+        let paths: Vec<_> = tracks.iter().map(|t| t.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["b", "d", "a"]);
+    }
+
+    #[test]
+    fn collation_ignores_case_and_diacritics() {
+        let collation = Collation::default();
+        assert_eq!(
+            collation.cmp(Some("Édith Piaf"), Some("Zappa")),
+            Ordering::Less
+        );
+        assert_eq!(collation.key("ÉDITH"), "edith");
+    }
+
+    #[test]
+    fn collation_ignores_leading_articles() {
+        let collation = Collation {
+            ignored_articles: vec!["The".to_owned(), "L'".to_owned()],
+        };
+        assert_eq!(collation.key("The Beatles"), "beatles");
+        assert_eq!(collation.key("L'Impératrice"), "imperatrice");
+        assert_eq!(collation.key("Theory of a Deadman"), "theory of a deadman");
+        assert_eq!(collation.key("The"), "the");
+    }
+
+    #[test]
+    fn sort_tags_take_priority() {
+        let mut beatles = track("a", "Album", None, None);
+        beatles.artists = vec!["The Beatles".to_owned()];
+        beatles.artist_sort = Some("Beatles, The".to_owned());
+        let mut cream = track("b", "Album", None, None);
+        cream.artists = vec!["Cream".to_owned()];
+
+        assert_eq!(
+            Track::compare_by_fields(&beatles, &cream, &[CachedField::Artist]),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn multiple_artists_roundtrip_through_cache() {
+        let mut collab = track("a", "Album", None, None);
+        collab.artists = vec!["A".to_owned(), "B".to_owned()];
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(&collab).unwrap();
+        let data = writer.into_inner().unwrap();
+        let mut reader = csv::Reader::from_reader(data.as_slice());
+        let read: Track = reader.deserialize().next().unwrap().unwrap();
+
+        assert_eq!(read.artists, collab.artists);
+        assert_eq!(read.cached_field_string(&CachedField::Artist), "A; B");
+    }
+
+    #[test]
+    fn album_artist_falls_back_to_artist() {
+        let mut compilation = track("a", "Album", None, Some(1));
+        compilation.album_artist = Some("Artist".to_owned());
+        compilation.artists = vec!["Someone Else".to_owned()];
+        let other = track("b", "Album", None, Some(2));
+
+        assert!(compilation.same_album(&other));
+    }
+
+    #[test]
+    fn track_filters() {
+        let mp3 = Track {
+            codec: Some("MP3".to_owned()),
+            bitrate: Some(128),
+            sample_rate: Some(44100),
+            ..Default::default()
+        };
+        let flac = Track {
+            codec: Some("FLAC".to_owned()),
+            bitrate: Some(900),
+            sample_rate: Some(96000),
+            bit_depth: Some(24),
+            ..Default::default()
+        };
+
+        let filter = |term| TrackFilter::parse(term).unwrap();
+        assert!(filter("is:lossless").matches(&flac));
+        assert!(!filter("is:lossless").matches(&mp3));
+        assert!(filter("IS:LOSSY").matches(&mp3));
+        let unknown = Track {
+            codec: Some(Track::UNKNOWN_CODEC.to_owned()),
+            ..Default::default()
+        };
+        assert!(!filter("is:lossy").matches(&unknown));
+        assert!(!filter("is:lossless").matches(&unknown));
+        assert!(filter("codec:mp3").matches(&mp3));
+        assert!(filter("bitrate<192").matches(&mp3));
+        assert!(!filter("bitrate<192").matches(&flac));
+        assert!(filter("samplerate>=96000").matches(&flac));
+        assert!(!filter("bitdepth>16").matches(&mp3));
+
+        let nas = Track {
+            root: Some("NAS".to_owned()),
+            ..Default::default()
+        };
+        assert!(filter("root:nas").matches(&nas));
+        assert!(!filter("root:nas").matches(&mp3));
+
+        let my_nas = Track {
+            root: Some("My NAS".to_owned()),
+            ..Default::default()
+        };
+        let terms = TrackFilter::split_query(r#"beatles  root:"my nas" "let it""#);
+        assert_eq!(terms, ["beatles", "root:my nas", "let it"]);
+        assert!(filter(&terms[1]).matches(&my_nas));
+        assert!(!filter(&terms[1]).matches(&nas));
+
+        assert_eq!(TrackFilter::parse("beatles"), None);
+        assert_eq!(TrackFilter::parse("bitrate<fast"), None);
+        assert_eq!(TrackFilter::parse("tempo>120"), None);
+    }
+
+    #[test]
+    fn infer_from_path_fills_missing_fields() {
+        let templates = [PathTemplate::parse("{artist}/{album}/{track} - {title}").unwrap()];
+        let mut track = Track {
+            path: PathBuf::from("/music/Artist/Album/03 - Song.mp3"),
+            album: Some("Tagged Album".to_owned()),
+            ..Default::default()
+        };
+
+        track.infer_from_path(Path::new("/music"), &templates);
+        assert_eq!(track.artists, ["Artist"]);
+        assert_eq!(track.album.as_deref(), Some("Tagged Album"));
+        assert_eq!(track.track_number, Some(3));
+        assert_eq!(track.title.as_deref(), Some("Song"));
+        assert_eq!(
+            track.inferred,
+            [
+                CachedField::Artist,
+                CachedField::TrackNumber,
+                CachedField::Title
+            ]
+        );
+        assert_eq!(
+            field_list::from_str(&field_list::to_string(&track.inferred)),
+            track.inferred
+        );
+
+        // Inferred values go away along with the templates
+        track.infer_from_path(Path::new("/music"), &[]);
+        assert!(track.artists.is_empty());
+        assert_eq!(track.title, None);
+        assert!(track.inferred.is_empty());
+    }
+
+    #[test]
+    fn format_duration_shows_hours_when_needed() {
+        assert_eq!(Track::format_duration(Duration::from_millis(999)), "0:00");
+        assert_eq!(Track::format_duration(Duration::from_secs(187)), "3:07");
+        assert_eq!(Track::format_duration(Duration::from_secs(3723)), "1:02:03");
+    }
+
+    #[test]
+    fn parse_duration_accepts_formatted_times() {
+        for secs in [0, 7, 187, 3723] {
+            let duration = Duration::from_secs(secs);
+            let formatted = Track::format_duration(duration);
+            assert_eq!(Track::parse_duration(&formatted), Some(duration));
+        }
+        assert_eq!(Track::parse_duration(" 90 "), Some(Duration::from_secs(90)));
+        assert_eq!(
+            Track::parse_duration("90:00"),
+            Some(Duration::from_secs(5400))
+        );
+
+        for invalid in ["", "3:7", "3:60", "-1:00", "1:2:3:4", "3:07.5", "a:bc"] {
+            assert_eq!(Track::parse_duration(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn progress_is_always_finite() {
+        let short = Track {
+            duration: Duration::from_millis(400),
+            ..Default::default()
+        };
+        assert_eq!(short.progress(Duration::from_millis(200)), 0.5);
+        assert_eq!(short.progress(Duration::from_secs(2)), 1.0);
+
+        let empty = Track::default();
+        assert_eq!(empty.progress(Duration::from_secs(1)), 0.0);
+    }
+
+    #[test]
+    fn sidecar_images_follow_pattern_order() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for file in ["Cover.JPG", "folder.png", "folder.txt", "song.flac"] {
+            std::fs::write(dir.join(file), []).unwrap();
+        }
+
+        let track = Track {
+            path: dir.join("song.flac"),
+            ..Default::default()
+        };
+        let patterns = [
+            Pattern::new("folder.*").unwrap(),
+            Pattern::new("cover.*").unwrap(),
+        ];
+        let images = track.sidecar_images(&patterns);
+
+        assert_eq!(images, [dir.join("folder.png"), dir.join("Cover.JPG")]);
+    }
+}